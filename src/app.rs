@@ -1,278 +1,931 @@
+use std::collections::{BTreeSet, VecDeque};
+use std::fs;
 use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
 
-use crossterm::event;
-use crossterm::event::{Event, KeyEventKind};
+use crossterm::{event, execute};
+use crossterm::event::{DisableMouseCapture, EnableMouseCapture};
+use crossterm::event::{Event, KeyEvent, KeyEventKind, MouseButton, MouseEvent, MouseEventKind};
 use ratatui::backend::Backend;
+use ratatui::layout::{Position, Rect};
 use ratatui::Terminal;
 use ratatui::widgets::TableState;
 
-use crate::{Config, Data, FIELD_SIZES, GameState, movement, PopUp, SelectedOption, WIN_VALUES};
+use crate::{Config, Data, Direction, FIELD_SIZES, GameState, menu, movement, puzzle, record, Screen, SelectedOption, settings, WIN_VALUES};
+use crate::colors::Theme;
 use crate::Direction::*;
 use crate::GameState::*;
 use crate::interface::ui;
-use crate::movement::rotate;
-use crate::PopUp::*;
-use crate::util::{check_loss, check_move, check_win, generate_data, incr_score, remove_matches, set_score, spawn_field};
+use crate::keymap::{Action, Context, KEYMAP_ROWS, KeyBinding, Lookup};
+use crate::menu::{MenuOption, Setting};
+use crate::movement::{BLOCKER, MergeRule, rotate, TRIPLE_WIN_VALUES};
+use crate::puzzle::{Goal, Puzzle};
+use crate::Screen::*;
+use crate::record::{BLOCKER_COUNTS, Category, GameRecord, GameResult, MOVE_LIMITS, StatsFilter, TIME_LIMITS, TRIPLE_SPAWN_VALUES, Variant};
+use crate::settings::ConfigWatcher;
+use crate::util::{check_loss, check_move, check_win, drag_direction, generate_data, get_score, incr_score, max_tile, new_seed, remove_matches, seed_rng, set_highscore, set_score, spawn_blockers, spawn_tiles, tile_count, GameTimer};
+
+// time between two moves when watching a replay
+const REPLAY_STEP: Duration = Duration::from_millis(400);
+// how often the config file is checked for changes while there is no input
+const CONFIG_POLL: Duration = Duration::from_millis(500);
+// how often a running countdown is redrawn
+const TICK: Duration = Duration::from_millis(200);
+// the highest tile the editor offers
+const EDITOR_MAX_TILE: u32 = 1 << 17;
 
 pub struct App {
     pub tablestate: TableState,
     pub gamestate: GameState,
     pub items: Vec<Data>,
     pub config: Config,
-    pub active_popup: PopUp,
+    // the screen on top is the one that is shown, the main menu is always at the bottom
+    pub screens: Vec<Layer>,
     pub selected_option: SelectedOption,
     pub option_lock: bool,
+    // areas of the last rendered frame, used to map mouse input
+    pub board_area: Rect,
+    pub popup_area: Rect,
+    pub drag_start: Option<(u16, u16)>,
+    pub record: GameRecord,
+    // remaining moves of the replay being watched
+    pub playback: Option<VecDeque<Direction>>,
+    pub notices: Vec<String>,
+    pub pending_keys: Vec<KeyBinding>,
+    // settings changed in game are written to the watched file unless they only apply for this session
+    pub watcher: Option<ConfigWatcher>,
+    pub session_only: bool,
+    // the settings changed for this session only, they are kept when the file is reloaded
+    pub session_keys: BTreeSet<String>,
+    pub help_scroll: u16,
+    pub history: Vec<GameResult>,
+    pub stats_filter: StatsFilter,
+    // mode and limits of the next game, chosen in the new game screen
+    pub variant: Variant,
+    pub time_limit: usize,
+    pub move_limit: usize,
+    pub blockers: usize,
+    pub triple_win_value: usize,
+    pub triple_spawns: usize,
+    // printed on exit, so the result of the daily challenge can be shared
+    pub daily_summary: Option<String>,
+    // merges of the current game, the history only keeps the total
+    pub merges: usize,
+    // the score after every move, for the summary at the end
+    pub score_history: Vec<u64>,
+    // only runs while the game can be seen and played
+    pub timer: GameTimer,
+    pub focused: bool,
+    pub replays: Vec<(PathBuf, GameRecord)>,
+    // the puzzles of all packs in the order they are listed and the ids of the solved ones
+    pub puzzles: Vec<Puzzle>,
+    pub solved: BTreeSet<String>,
+    // the board of the sandbox, it is kept until the program exits
+    pub editor: Vec<Data>,
+    // row and column of the selected field in the editor
+    pub cursor: (usize, usize),
+    pub sandbox_spawns: bool,
+    pub quit: bool,
+}
+
+// a screen on the stack, remembers the selected row for when it is shown again
+pub struct Layer {
+    pub screen: Screen,
+    selected: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MenuEntry {
+    Continue,
+    NewGame,
+    Daily,
+    Puzzles,
+    Sandbox,
+    Stats,
+    Replays,
+    Settings,
+    Quit,
+}
+
+impl MenuEntry {
+    pub fn name(&self) -> &'static str {
+        match self {
+            MenuEntry::Continue => "Continue",
+            MenuEntry::NewGame => "New Game",
+            MenuEntry::Daily => "Daily Challenge",
+            MenuEntry::Puzzles => "Puzzles",
+            MenuEntry::Sandbox => "Sandbox",
+            MenuEntry::Stats => "Statistics",
+            MenuEntry::Replays => "Replays",
+            MenuEntry::Settings => "Settings",
+            MenuEntry::Quit => "Quit",
+        }
+    }
+}
+
+// what a screen does with the input, every screen has exactly one entry in ScreenHandler::of
+struct ScreenHandler {
+    // the rows that can be selected with up and down
+    rows: fn(&App) -> usize,
+    // the settings shown on the screen, they come first in its rows
+    options: fn(&App) -> Vec<MenuOption>,
+    up_down: fn(&mut App, Direction),
+    // called with true for right
+    left_right: fn(&mut App, bool),
+    // called with the selected row
+    confirm: fn(&mut App, usize),
+    back: fn(&mut App),
+    context: Context,
+    // called with the line that was clicked, counted from the top border of the screen
+    click: fn(&mut App, u16),
+    // the actions that have their own key on every screen
+    reset: fn(&mut App),
+    config: fn(&mut App),
+    help: fn(&mut App),
+}
+
+impl ScreenHandler {
+    // a menu screen that can be closed and whose rows do nothing yet
+    fn menu(rows: fn(&App) -> usize) -> Self {
+        Self {
+            rows,
+            options: |_| Vec::new(),
+            up_down: App::select_row,
+            left_right: |_, _| {},
+            confirm: |_, _| {},
+            back: App::pop,
+            context: Context::Menu,
+            click: |app, line| app.click_row(line, true),
+            reset: |_| {},
+            config: |_| {},
+            help: App::open_help,
+        }
+    }
+
+    fn of(screen: Screen) -> Self {
+        match screen {
+            MainMenu => Self {
+                confirm: |app, selected| app.confirm_menu_entry(app.menu_entries()[selected]),
+                // the main menu can't be closed
+                back: |_| {},
+                config: |app| app.push(Screen::Config),
+                ..Self::menu(|app| app.menu_entries().len())
+            },
+            NewGame => Self {
+                options: |app| menu::new_game_options(app.variant),
+                left_right: App::change_option,
+                // the last row starts the game
+                confirm: |app, selected| {
+                    if app.confirm_option(selected).is_none() {
+                        app.suspend_game();
+                        app.new_game(Option::None);
+                        app.pop();
+                        app.push(Game);
+                    }
+                },
+                ..Self::menu(|app| app.options().len() + 1)
+            },
+            // only the first row can be selected, the past results are below it
+            Screen::Daily => Self {
+                confirm: |app, _| {
+                    app.suspend_game();
+                    app.start_daily(false);
+                    app.push(Game);
+                },
+                ..Self::menu(|_| 1)
+            },
+            Puzzles => Self {
+                confirm: |app, selected| {
+                    let Some(puzzle) = app.puzzles.get(selected).cloned() else { return };
+                    app.suspend_game();
+                    app.start_puzzle(puzzle);
+                    app.push(Game);
+                },
+                ..Self::menu(|app| app.puzzles.len())
+            },
+            Sandbox => Self {
+                options: |_| menu::sandbox_options(),
+                left_right: App::change_option,
+                // the last row starts the game
+                confirm: |app, selected| {
+                    if app.confirm_option(selected).is_none() {
+                        app.start_sandbox();
+                    }
+                },
+                ..Self::menu(|app| app.options().len() + 1)
+            },
+            // the editor has its own cursor, which all four directions move or change the value under
+            Editor => Self {
+                up_down: App::edit,
+                left_right: |app, forward| app.edit(if forward { Right } else { Left }),
+                confirm: |app, _| app.option_lock = true,
+                click: |_, _| {},
+                reset: |app| app.editor.iter_mut().for_each(|row| row.numbers.fill(0)),
+                ..Self::menu(|_| 0)
+            },
+            Game => Self {
+                up_down: App::shift,
+                left_right: |app, forward| app.shift(if forward { Right } else { Left }),
+                context: Context::Game,
+                click: |_, _| {},
+                reset: |app| if app.config.reset_popup { app.push(Reset) } else { app.play_again() },
+                config: |app| app.push(Screen::Config),
+                ..Self::menu(|_| 0)
+            },
+            Reset => Self {
+                left_right: |app, _| app.toggle_option(),
+                confirm: |app, _| app.confirm_reset(),
+                back: |app| {
+                    // the game can't be left without choosing between a new game and the menu once it is over
+                    if app.gamestate == Active {
+                        app.selected_option = SelectedOption::default();
+                        app.pop();
+                    }
+                },
+                context: Context::Popup,
+                click: App::click_reset,
+                reset: |app| {
+                    if app.gamestate == Active {
+                        app.pop();
+                    }
+                },
+                ..Self::menu(|_| 0)
+            },
+            // a setting has to be selected with confirm before it can be changed
+            Screen::Config => Self {
+                options: |_| menu::config_options(),
+                left_right: |app, forward| {
+                    if app.option_lock {
+                        app.change_option(forward);
+                    }
+                },
+                confirm: |app, selected| {
+                    if app.confirm_option(selected).is_some_and(|setting| !matches!(setting, Setting::Submenu(_))) {
+                        app.option_lock = true;
+                    }
+                },
+                config: App::pop,
+                ..Self::menu(|app| app.options().len())
+            },
+            // the bindings are only listed, they are changed in the keymap section of the config file
+            Keymap => Self {
+                confirm: |app, _| app.pop(),
+                click: |app, line| app.click_row(line, false),
+                ..Self::menu(|_| KEYMAP_ROWS.len())
+            },
+            Colors => Self {
+                confirm: |app, selected| {
+                    app.config.theme = Theme::ALL[selected];
+                    app.save_setting("theme");
+                },
+                click: |app, line| app.click_row(line, false),
+                ..Self::menu(|_| Theme::ALL.len())
+            },
+            Notice => Self {
+                confirm: |app, _| app.close_notice(),
+                back: App::close_notice,
+                context: Context::Popup,
+                click: |_, _| {},
+                ..Self::menu(|_| 0)
+            },
+            // the interface keeps it from scrolling past the end
+            Help => Self {
+                up_down: |app, dir| match dir {
+                    Up => app.help_scroll = app.help_scroll.saturating_sub(1),
+                    Down => app.help_scroll = app.help_scroll.saturating_add(1),
+                    Left | Right => {}
+                },
+                confirm: |app, _| app.pop(),
+                click: |_, _| {},
+                help: App::pop,
+                ..Self::menu(|_| 0)
+            },
+            // the filters
+            Stats => Self {
+                left_right: App::change_filter,
+                click: |app, line| app.click_row(line, false),
+                ..Self::menu(|_| 2)
+            },
+            Replays => Self {
+                confirm: |app, selected| {
+                    let Some((_, replay)) = app.replays.get(selected).cloned() else { return };
+                    app.suspend_game();
+                    app.start_replay(replay);
+                    app.push(Game);
+                },
+                ..Self::menu(|app| app.replays.len())
+            },
+        }
+    }
 }
 
 impl App {
-    pub fn new(config: Config) -> App {
-        let data_vec = generate_data();
-        App {
-            tablestate: TableState::default(),
+    pub fn new(config: Config, seed: Option<u64>) -> App {
+        let mut app = App {
+            tablestate: TableState::default().with_selected(Some(0)),
             gamestate: Active,
-            items: data_vec,
+            items: Vec::new(),
+            record: GameRecord::new(0, 0, 0),
+            playback: Option::None,
+            notices: Vec::new(),
+            pending_keys: Vec::new(),
+            watcher: Option::None,
+            session_only: false,
+            session_keys: BTreeSet::new(),
+            help_scroll: 0,
+            history: Vec::new(),
+            stats_filter: StatsFilter::default(),
+            variant: Variant::Classic,
+            time_limit: 0,
+            move_limit: 1,
+            blockers: 1,
+            triple_win_value: 4,
+            triple_spawns: 0,
+            daily_summary: Option::None,
+            merges: 0,
+            score_history: Vec::new(),
+            timer: GameTimer::new(Duration::ZERO),
+            focused: true,
+            replays: Vec::new(),
+            puzzles: Vec::new(),
+            solved: BTreeSet::new(),
+            editor: Vec::new(),
+            cursor: (0, 0),
+            sandbox_spawns: true,
+            quit: false,
             config,
-            active_popup: None,
+            screens: vec![Layer { screen: MainMenu, selected: 0 }],
             selected_option: SelectedOption::default(),
             option_lock: false,
+            board_area: Rect::default(),
+            popup_area: Rect::default(),
+            drag_start: Option::None,
+        };
+        app.resize_editor(FIELD_SIZES[app.config.field_size] as usize);
+        app.new_game(seed);
+        app
+    }
+
+    // continues a saved game by repeating all of its moves
+    pub fn load(config: Config, save: GameRecord) -> App {
+        let mut app = App::new(config, Option::None);
+        app.load_game(save);
+        app
+    }
+
+    pub fn replay(config: Config, replay: GameRecord) -> App {
+        let mut app = App::new(config, Option::None);
+        app.start_replay(replay);
+        app
+    }
+
+    pub fn screen(&self) -> Screen {
+        self.screens.last().map(|layer| layer.screen).unwrap_or(MainMenu)
+    }
+
+    pub fn push(&mut self, screen: Screen) {
+        if let Some(layer) = self.screens.last_mut() {
+            layer.selected = self.tablestate.selected().unwrap_or(0);
         }
+        self.screens.push(Layer { screen, selected: 0 });
+        self.tablestate = TableState::default().with_selected(Some(0));
+        self.option_lock = false;
     }
 
-    pub fn up(&mut self) {
-        if self.option_lock {
-            return;
+    // the main menu can't be closed
+    pub fn pop(&mut self) {
+        if self.screens.len() > 1 {
+            self.screens.pop();
         }
+        self.tablestate = TableState::default().with_selected(self.screens.last().map(|layer| layer.selected));
+        self.option_lock = false;
+    }
 
-        match self.active_popup {
-            None => {
-                let mut new_items = Vec::<Data>::new();
-                let mut clone = self.items.clone();
-                rotate(clone.as_mut_slice(), true);
-                let spawn = check_move(&clone, Left);
+    fn pop_to_menu(&mut self) {
+        while self.screens.len() > 1 {
+            self.pop();
+        }
+    }
 
-                for row in clone.iter() {
-                    let mut slide = movement::slide_left(row.numbers().as_slice());
-                    new_items.push(Data { numbers: slide.clone() });
+    // number of rows that can be selected on the current screen
+    pub fn rows(&self) -> usize {
+        (ScreenHandler::of(self.screen()).rows)(self)
+    }
 
-                    remove_matches(&mut slide, &mut row.numbers.clone());
-                    incr_score(slide.iter().map(|i| *i as u64).sum());
-                }
+    pub fn menu_entries(&self) -> Vec<MenuEntry> {
+        let mut entries = vec![MenuEntry::NewGame, MenuEntry::Daily, MenuEntry::Puzzles, MenuEntry::Sandbox, MenuEntry::Stats, MenuEntry::Replays, MenuEntry::Settings, MenuEntry::Quit];
+        if self.game_in_progress() || record::save_path().is_file() {
+            entries.insert(0, MenuEntry::Continue);
+        }
+        entries
+    }
+
+    fn game_in_progress(&self) -> bool {
+        self.playback.is_none() && self.gamestate == Active && !self.record.moves.is_empty()
+    }
 
-                rotate(new_items.as_mut_slice(), false);
-                self.items = new_items;
+    pub fn show_notices(&mut self, notices: Vec<String>) {
+        if !notices.is_empty() {
+            self.notices = notices;
+            if self.screen() != Notice {
+                self.push(Notice);
+            }
+        }
+    }
 
-                if spawn { spawn_field(&mut self.items) }
-                if check_win(&self.items, &WIN_VALUES[self.config.win_value]) {
-                    self.gamestate = Win;
-                    self.active_popup = Reset;
+    // the options of the settings screens, empty for every other screen
+    pub fn options(&self) -> Vec<MenuOption> {
+        (ScreenHandler::of(self.screen()).options)(self)
+    }
+
+    // changes in the config screen are saved right away, the ones for a new game are not
+    fn change_option(&mut self, forward: bool) {
+        let Some(option) = self.options().into_iter().nth(self.tablestate.selected().unwrap_or(0)) else {
+            return;
+        };
+        option.setting.change(self, forward);
+        match (self.screen(), option.key) {
+            (Screen::Config, Some(key)) => self.save_setting(key),
+            // the choices on the other screens aren't saved, but they shouldn't be lost on a reload either
+            (_, Some(key)) => { self.session_keys.insert(String::from(key)); }
+            _ => {}
+        }
+    }
+
+    fn save_setting(&mut self, key: &str) {
+        if self.session_only {
+            self.session_keys.insert(String::from(key));
+            return;
+        }
+        self.session_keys.remove(key);
+        if let Some(watcher) = &mut self.watcher {
+            match settings::save_setting(&watcher.path, &self.config, key) {
+                Ok(()) => {
+                    watcher.refresh();
+                    // the command line would replace it again on the next reload
+                    if watcher.overrides(key) {
+                        self.session_keys.insert(String::from(key));
+                    }
                 }
-                if check_loss(&self.items) {
-                    self.gamestate = Loss;
-                    self.active_popup = Reset;
+                Err(e) => {
+                    let message = format!("Could not save the settings to {}: {e}", watcher.path.display());
+                    self.option_lock = false;
+                    self.show_notices(vec![message]);
                 }
             }
-            Reset => {
-                // nothing :)
-            }
-            PopUp::Config => {
-                self.tablestate.select(Some((self.tablestate.selected().unwrap() as i32 - 1).rem_euclid(7) as usize));
-            }
-            Keymap => {
-                self.tablestate.select(Some((self.tablestate.selected().unwrap() as i32 - 1).rem_euclid(8) as usize));
-            }
-            Colors => {
-                self.tablestate.select(Some((self.tablestate.selected().unwrap() as i32 - 1).rem_euclid(5) as usize));
+        }
+    }
+
+    // applies the config file again if it was changed, returns whether it was reloaded
+    pub fn reload_config(&mut self) -> bool {
+        let Some(watcher) = &mut self.watcher else {
+            return false;
+        };
+        if !watcher.changed() {
+            return false;
+        }
+
+        // board size and win value are part of the running game, so they only apply to the next one
+        let (config, notices) = watcher.load();
+        self.config = settings::keep_settings(config, &self.config, &self.session_keys);
+        self.show_notices(notices);
+        true
+    }
+
+    // starts a game with the settings of the config and the new game screen
+    pub fn new_game(&mut self, seed: Option<u64>) {
+        let win_value = match self.variant {
+            Variant::Triples => TRIPLE_WIN_VALUES[self.triple_win_value],
+            variant => variant.merge_rule().win_values()[self.config.win_value],
+        };
+        let spawns = match self.variant {
+            Variant::Triples => self.config.spawns.clone().with_values(TRIPLE_SPAWN_VALUES[self.triple_spawns]),
+            // the weights of the config are for powers of two
+            variant => match variant.merge_rule() {
+                MergeRule::Doubling => self.config.spawns.clone(),
+                rule => self.config.spawns.clone().with_values(rule.spawn_values()),
+            },
+        };
+        let record = GameRecord::new(seed.unwrap_or_else(new_seed), FIELD_SIZES[self.config.field_size], win_value);
+        self.start_game(GameRecord {
+            variant: self.variant,
+            time_limit: (self.variant == Variant::Blitz).then_some(TIME_LIMITS[self.time_limit]),
+            move_limit: (self.variant == Variant::LimitedMoves).then_some(MOVE_LIMITS[self.move_limit]),
+            blockers: if self.variant == Variant::Blockers { BLOCKER_COUNTS[self.blockers] } else { 0 },
+            spawns: Some(spawns),
+            ..record
+        });
+    }
+
+    // the moves of the record are ignored
+    fn start_game(&mut self, record: GameRecord) {
+        seed_rng(record.seed);
+        let size = record.field_size;
+        // every variant and limit has its own highscore
+        set_highscore(record::read_history().iter().filter(|r| r.category() == record.category()).map(|r| r.score).max().unwrap_or(0));
+        self.record = GameRecord { moves: Vec::new(), elapsed: Duration::ZERO, ..record };
+        self.items = match (&self.record.puzzle, &self.record.board) {
+            (Some(puzzle), _) => puzzle.field(),
+            (_, Some(board)) => board.iter().map(|row| Data { numbers: row.clone() }).collect(),
+            (None, None) => {
+                let mut field = generate_data(size as usize, &self.record.spawn_rules(), self.record.variant.merge_rule());
+                spawn_blockers(&mut field, self.record.blockers);
+                field
             }
+        };
+        self.gamestate = Active;
+        self.playback = Option::None;
+        self.merges = 0;
+        self.score_history.clear();
+        self.timer = GameTimer::new(Duration::ZERO);
+        set_score(0);
+    }
+
+    // records with a size or win value that can't be chosen fall back to the config, puzzles bring their own
+    fn start_record(&mut self, record: &GameRecord) {
+        if record.puzzle.is_some() || record.board.is_some() {
+            return self.start_game(record.clone());
         }
+        let size = if FIELD_SIZES.contains(&record.field_size) { record.field_size } else { FIELD_SIZES[self.config.field_size] };
+        let win_values = record.variant.merge_rule().win_values();
+        let win_value = if win_values.contains(&record.win_value) { record.win_value } else { win_values[self.config.win_value.min(win_values.len() - 1)] };
+        self.start_game(GameRecord { field_size: size, win_value, ..record.clone() });
     }
 
-    pub fn down(&mut self) {
+    pub fn load_game(&mut self, save: GameRecord) {
+        self.start_record(&save);
+        for dir in save.moves {
+            self.shift(dir);
+        }
+        self.timer = GameTimer::new(save.elapsed);
+    }
+
+    // the record with the time played so far, for saving it
+    pub fn saved_record(&self) -> GameRecord {
+        GameRecord { elapsed: self.timer.elapsed(), ..self.record.clone() }
+    }
+
+    // pauses the timer while popups are open, the terminal isn't focused or the game is over
+    pub fn update_timer(&mut self) {
+        let running = self.screen() == Game && self.gamestate == Active && self.focused && self.playback.is_none();
+        self.timer.set_running(running);
+    }
+
+    // called regularly by the main loop, ends blitz games when their time is up
+    pub fn tick(&mut self) {
+        self.update_timer();
+        if self.gamestate == Active && self.playback.is_none() && self.time_left() == Some(Duration::ZERO) {
+            self.gamestate = TimeOut;
+            self.push(Reset);
+            self.finish_game();
+        }
+    }
+
+    pub fn moves_left(&self) -> Option<usize> {
+        self.record.move_limit.map(|limit| limit.saturating_sub(self.record.moves.len()))
+    }
+
+    // everyone gets the same spawns on the same day, the board and win value are fixed for the same reason
+    // practice runs are never official, even if today's attempt is still open
+    fn start_daily(&mut self, practice: bool) {
+        let date = record::today();
+        let official = !practice && record::daily_available(&record::read_history(), &date);
+        if official {
+            // there is no way to report errors while the game is running, at worst the attempt can be started again
+            let _ = record::mark_daily_started(&date);
+        }
+        let record = GameRecord::new(record::daily_seed(&date), FIELD_SIZES[1], WIN_VALUES[8]);
+        self.start_game(GameRecord {
+            variant: Variant::Daily,
+            date: official.then_some(date),
+            ..record
+        });
+    }
+
+    // the spawns that aren't fixed by the puzzle are still random
+    fn start_puzzle(&mut self, puzzle: Puzzle) {
+        let record = GameRecord::new(new_seed(), puzzle.board.len() as u16, match puzzle.goal {
+            Goal::Reach { tile } => tile,
+            Goal::SingleTile => 0,
+        });
+        self.start_game(GameRecord {
+            variant: Variant::Puzzle,
+            move_limit: puzzle.moves,
+            puzzle: Some(puzzle),
+            ..record
+        });
+    }
+
+    fn open_puzzles(&mut self) {
+        let (packs, errors) = puzzle::load_packs();
+        self.puzzles = packs.into_iter().flat_map(|pack| pack.puzzles).collect();
+        self.solved = puzzle::read_solved();
+        self.push(Puzzles);
+        self.show_notices(errors);
+    }
+
+    // starts a game with the same rules as the current one, which isn't always the one the new game screen shows
+    // puzzles and sandbox games start from their board again, daily challenges with today's tiles and the others get new random tiles
+    fn play_again(&mut self) {
+        match self.record.variant {
+            // today's challenge again, as practice
+            Variant::Daily => self.start_daily(true),
+            _ => self.start_game(GameRecord { seed: new_seed(), ..self.record.clone() }),
+        }
+    }
+
+    // a board that is already lost ends right away, which helps to find out why
+    fn start_sandbox(&mut self) {
+        if tile_count(&self.editor) == 0 {
+            self.show_notices(vec![String::from("Put at least one tile on the board first.")]);
+            return;
+        }
+
+        self.suspend_game();
+        let record = GameRecord::new(new_seed(), self.editor.len() as u16, 0);
+        self.start_game(GameRecord {
+            variant: Variant::Sandbox,
+            board: Some(self.editor.iter().map(|row| row.numbers.clone()).collect()),
+            no_spawns: !self.sandbox_spawns,
+            spawns: Some(self.config.spawns.clone()),
+            ..record
+        });
+        self.push(Game);
+        self.check_end();
+    }
+
+    pub fn resize_editor(&mut self, size: usize) {
+        self.editor.resize(size, Data { numbers: Vec::new() });
+        for row in self.editor.iter_mut() {
+            row.numbers.resize(size, 0);
+        }
+        self.cursor = (self.cursor.0.min(size - 1), self.cursor.1.min(size - 1));
+    }
+
+    // moves the cursor, or changes the selected field while it is being edited
+    fn edit(&mut self, dir: Direction) {
+        let size = self.editor.len();
+        let (row, column) = self.cursor;
         if self.option_lock {
+            let value = &mut self.editor[row].numbers[column];
+            // below an empty field comes a blocker
+            *value = match (dir, *value) {
+                (Up | Right, 0) => 2,
+                (Up | Right, BLOCKER) => 0,
+                (Up | Right, value) => (value * 2).min(EDITOR_MAX_TILE),
+                (Down | Left, 0 | BLOCKER) => BLOCKER,
+                (Down | Left, value) => if value == 2 { 0 } else { value / 2 },
+            };
             return;
         }
 
-        match self.active_popup {
-            None => {
-                let mut new_items = Vec::<Data>::new();
-                let mut clone = self.items.clone();
-                rotate(clone.as_mut_slice(), false);
-                let spawn = check_move(&clone, Left);
+        self.cursor = match dir {
+            Up => (row.saturating_sub(1), column),
+            Down => ((row + 1).min(size - 1), column),
+            Left => (row, column.saturating_sub(1)),
+            Right => (row, (column + 1).min(size - 1)),
+        };
+    }
 
-                for row in clone.iter() {
-                    let mut slide = movement::slide_left(row.numbers().as_slice());
-                    new_items.push(Data { numbers: slide.clone() });
+    pub fn time_left(&self) -> Option<Duration> {
+        self.record.time_limit.map(|limit| limit.saturating_sub(self.timer.elapsed()))
+    }
 
-                    remove_matches(&mut slide, &mut row.numbers.clone());
-                    incr_score(slide.iter().map(|i| *i as u64).sum());
-                }
+    pub fn start_replay(&mut self, replay: GameRecord) {
+        self.start_record(&replay);
+        self.playback = Some(replay.moves.into());
+    }
+
+    // leaves the replay and goes back to where it was started from
+    pub fn stop_replay(&mut self) {
+        self.new_game(Option::None);
+        while matches!(self.screen(), Game | Reset) && self.screens.len() > 1 {
+            self.pop();
+        }
+    }
 
-                rotate(new_items.as_mut_slice(), true);
-                self.items = new_items;
+    // the game in progress is saved before another one replaces it, so it can still be continued from the menu
+    fn suspend_game(&mut self) {
+        if self.game_in_progress() {
+            let _ = self.saved_record().write(&record::save_path());
+        }
+    }
 
-                if spawn { spawn_field(&mut self.items) }
-                if check_win(&self.items, &WIN_VALUES[self.config.win_value]) {
-                    self.gamestate = Win;
-                    self.active_popup = Reset;
+    fn continue_game(&mut self) {
+        if !self.game_in_progress() {
+            match GameRecord::read(&record::save_path()) {
+                Ok(save) => {
+                    // it is saved again on exit if it isn't finished by then
+                    let _ = fs::remove_file(record::save_path());
+                    self.load_game(save);
                 }
-                if check_loss(&self.items) {
-                    self.gamestate = Loss;
-                    self.active_popup = Reset;
+                Err(e) => {
+                    self.show_notices(vec![format!("Could not load {}: {e}", record::save_path().display())]);
+                    return;
                 }
             }
-            Reset => {
-                // nothing :)
-            }
-            PopUp::Config => {
-                self.tablestate.select(Some((self.tablestate.selected().unwrap() + 1) % 7));
-            }
-            Keymap => {
-                self.tablestate.select(Some((self.tablestate.selected().unwrap() + 1) % 8));
-            }
-            Colors => {
-                self.tablestate.select(Some((self.tablestate.selected().unwrap() + 1) % 5));
-            }
         }
+        self.push(Game);
     }
 
-    pub fn left(&mut self) {
-        match self.active_popup {
-            None => {
-                let mut new_items = Vec::<Data>::new();
-                let spawn = check_move(&self.items, Left);
+    // moves all tiles into the given direction, this is the only place where the game field changes
+    pub fn shift(&mut self, dir: Direction) {
+        let mut new_items = Vec::<Data>::new();
+        let mut clone = self.items.clone();
+        let rule = self.record.variant.merge_rule();
 
-                for row in self.items.iter() {
-                    let mut slide = movement::slide_left(row.numbers().as_slice());
-                    new_items.push(Data { numbers: slide.clone() });
+        // up and down are handled as left moves on the rotated field
+        match dir {
+            Up => rotate(clone.as_mut_slice(), true),
+            Down => rotate(clone.as_mut_slice(), false),
+            Left | Right => {}
+        }
+        let spawn = check_move(&clone, if dir == Right { Right } else { Left }, rule);
 
-                    remove_matches(&mut slide, &mut row.numbers.clone());
-                    incr_score(slide.iter().map(|i| *i as u64).sum());
-                }
+        for row in clone.iter() {
+            let mut slide = if dir == Right {
+                movement::slide_right(row.numbers().as_slice(), rule)
+            } else {
+                movement::slide_left(row.numbers().as_slice(), rule)
+            };
+            new_items.push(Data { numbers: slide.clone() });
 
-                self.items = new_items;
+            // blockers stay where they are, so they never count as merged
+            slide.retain(|value| *value != BLOCKER);
+            remove_matches(&mut slide, &mut row.numbers.iter().copied().filter(|value| *value != BLOCKER).collect());
+            incr_score(slide.iter().map(|i| *i as u64).sum());
+        }
 
-                if spawn { spawn_field(&mut self.items) }
-                if check_win(&self.items, &WIN_VALUES[self.config.win_value]) {
-                    self.gamestate = Win;
-                    self.active_popup = Reset;
-                }
-                if check_loss(&self.items) {
-                    self.gamestate = Loss;
-                    self.active_popup = Reset;
-                }
-            }
-            Reset => {
-                if self.selected_option == SelectedOption::No {
-                    self.selected_option = SelectedOption::Yes
-                } else {
-                    self.selected_option = SelectedOption::No
-                }
+        match dir {
+            Up => rotate(new_items.as_mut_slice(), false),
+            Down => rotate(new_items.as_mut_slice(), true),
+            Left | Right => {}
+        }
+        // every merge turns a group of tiles into one, so it removes all but one of them
+        self.merges += (tile_count(&self.items) - tile_count(&new_items)) / (rule.group() - 1);
+        self.items = new_items;
+
+        if spawn {
+            match &self.record.puzzle {
+                Some(puzzle) => puzzle.spawn(&mut self.items, self.record.moves.len()),
+                None if self.record.no_spawns => {}
+                None => spawn_tiles(&mut self.items, &self.record.spawn_rules(), rule, Some(dir)),
             }
-            PopUp::Config => {
-                if self.option_lock {
-                    match self.tablestate.selected().unwrap() {
-                        2 => {
-                            if self.config.field_size > 0 {
-                                self.config.field_size -= 1
-                            }
-                        }
-                        3 => {
-                            if self.config.win_value > 0 {
-                                self.config.win_value -= 1
-                            }
-                        }
-                        4 => self.config.reset_popup = !self.config.reset_popup,
-                        5 => self.config.ending_animation = !self.config.ending_animation,
-                        6 => self.config.control_info = !self.config.control_info,
-                        _ => unimplemented!()
-                    }
-                }
+            self.record.moves.push(dir);
+            self.score_history.push(get_score());
+        }
+        self.check_end();
+    }
+
+    fn check_end(&mut self) {
+        if self.goal_reached() {
+            self.gamestate = Win;
+        }
+        if check_loss(&self.items, self.record.variant.merge_rule()) {
+            self.gamestate = Loss;
+        }
+        if self.gamestate == Active && self.moves_left() == Some(0) {
+            self.gamestate = OutOfMoves;
+        }
+        if self.gamestate != Active {
+            self.push(Reset);
+            self.finish_game();
+        }
+    }
+
+    fn goal_reached(&self) -> bool {
+        match &self.record.puzzle {
+            Some(puzzle) => puzzle.goal.reached(&self.items),
+            None => !matches!(self.record.variant, Variant::Blitz | Variant::Sandbox) && check_win(&self.items, &self.record.win_value),
+        }
+    }
+
+    fn finish_game(&mut self) {
+        self.update_timer();
+        if self.playback.is_some() {
+            return;
+        }
+        self.record.elapsed = self.timer.elapsed();
+
+        let result = GameResult {
+            timestamp: record::timestamp(),
+            field_size: self.record.field_size,
+            win_value: self.record.win_value,
+            score: get_score(),
+            max_tile: max_tile(&self.items),
+            moves: self.record.moves.len(),
+            won: self.gamestate == Win,
+            merges: self.merges,
+            variant: self.record.variant,
+            date: self.record.date.clone(),
+            time_limit: self.record.time_limit,
+            move_limit: self.record.move_limit,
+        };
+        let _ = record::write_replay(&self.record);
+
+        if let Some(puzzle) = &self.record.puzzle {
+            if result.won {
+                let _ = puzzle::mark_solved(puzzle);
+                self.solved.insert(puzzle.id());
             }
-            Keymap | Colors => {
-                // nothing :)
+            return;
+        }
+        if self.record.variant == Variant::Sandbox {
+            return;
+        }
+
+        // only the first attempt of a daily challenge counts
+        if self.record.variant == Variant::Daily {
+            if self.record.date.is_none() {
+                return;
             }
+            self.daily_summary = Some(record::daily_summary(&result));
+        }
+        // there is no way to report errors while the game is running, losing a record is not worth a crash
+        let _ = record::append_history(result);
+    }
+
+    pub fn step_replay(&mut self) {
+        // the replay only runs while it can be seen
+        if self.screen() != Game {
+            return;
+        }
+        if let Some(dir) = self.playback.as_mut().and_then(|moves| moves.pop_front()) {
+            self.shift(dir);
         }
     }
 
+    pub fn up(&mut self) {
+        (ScreenHandler::of(self.screen()).up_down)(self, Up);
+    }
+
+    pub fn down(&mut self) {
+        (ScreenHandler::of(self.screen()).up_down)(self, Down);
+    }
+
+    pub fn left(&mut self) {
+        (ScreenHandler::of(self.screen()).left_right)(self, false);
+    }
+
     pub fn right(&mut self) {
-        match self.active_popup {
-            None => {
-                let mut new_items = Vec::<Data>::new();
-                let spawn = check_move(&self.items, Right);
+        (ScreenHandler::of(self.screen()).left_right)(self, true);
+    }
 
-                for row in self.items.iter() {
-                    let mut slide = movement::slide_right(row.numbers().as_slice());
-                    new_items.push(Data { numbers: slide.clone() });
+    // up and down on screens with rows, a setting that is being changed keeps its row
+    fn select_row(&mut self, dir: Direction) {
+        let rows = self.rows();
+        if self.option_lock || rows == 0 {
+            return;
+        }
 
-                    remove_matches(&mut slide, &mut row.numbers.clone());
-                    incr_score(slide.iter().map(|i| *i as u64).sum());
-                }
+        let selected = self.tablestate.selected().unwrap_or(0);
+        match dir {
+            Up => self.tablestate.select(Some((selected + rows - 1) % rows)),
+            Down => self.tablestate.select(Some((selected + 1) % rows)),
+            Left | Right => {}
+        }
+    }
 
-                self.items = new_items;
+    // the first row filters by board size, the second by variant and limit, both start with all games
+    fn change_filter(&mut self, forward: bool) {
+        fn step<T: Copy + PartialEq>(current: Option<T>, values: &[T], forward: bool) -> Option<T> {
+            let options = std::iter::once(Option::None).chain(values.iter().copied().map(Some)).collect::<Vec<_>>();
+            let index = options.iter().position(|o| *o == current).unwrap_or(0);
+            options[if forward { (index + 1).min(options.len() - 1) } else { index.saturating_sub(1) }]
+        }
 
-                if spawn { spawn_field(&mut self.items) }
-                if check_win(&self.items, &WIN_VALUES[self.config.win_value]) {
-                    self.gamestate = Win;
-                    self.active_popup = Reset;
-                }
-                if check_loss(&self.items) {
-                    self.gamestate = Loss;
-                    self.active_popup = Reset;
-                }
-            }
-            Reset => {
-                if self.selected_option == SelectedOption::No {
-                    self.selected_option = SelectedOption::Yes
-                } else {
-                    self.selected_option = SelectedOption::No
-                }
-            }
-            PopUp::Config => {
-                if self.option_lock {
-                    match self.tablestate.selected().unwrap() {
-                        2 => {
-                            if self.config.field_size < FIELD_SIZES.len()-1 {
-                                self.config.field_size += 1
-                            }
-                        }
-                        3 => {
-                            if self.config.win_value < WIN_VALUES.len()-1 {
-                                self.config.win_value += 1
-                            }
-                        }
-                        4 => self.config.reset_popup = !self.config.reset_popup,
-                        5 => self.config.ending_animation = !self.config.ending_animation,
-                        6 => self.config.control_info = !self.config.control_info,
-                        _ => unimplemented!()
-                    }
-                }
-            }
-            Keymap | Colors => {
-                // nothing :)
-            }
+        match self.tablestate.selected() {
+            Some(0) => self.stats_filter.field_size = step(self.stats_filter.field_size, &FIELD_SIZES, forward),
+            Some(1) => self.stats_filter.category = step(self.stats_filter.category, &Category::all(), forward),
+            _ => {}
         }
     }
 
-    pub fn reset(&mut self) {
-        if self.config.reset_popup {
-            if self.active_popup == None {
-                self.active_popup = Reset;
-            } else if self.active_popup == Reset && self.gamestate == Active {
-                self.active_popup = None;
-            }
+    fn toggle_option(&mut self) {
+        if self.selected_option == SelectedOption::No {
+            self.selected_option = SelectedOption::Yes
         } else {
-            self.items = generate_data();
-            set_score(0);
+            self.selected_option = SelectedOption::No
         }
     }
 
+    pub fn reset(&mut self) {
+        (ScreenHandler::of(self.screen()).reset)(self);
+    }
+
     pub fn config(&mut self) {
-        if self.active_popup == None {
-            self.tablestate.select(Some(0));
-            self.active_popup = PopUp::Config;
-        } else if self.active_popup == PopUp::Config {
-            self.option_lock = false;
-            self.active_popup = None;
-        }
+        (ScreenHandler::of(self.screen()).config)(self);
+    }
+
+    pub fn help(&mut self) {
+        (ScreenHandler::of(self.screen()).help)(self);
+    }
+
+    fn open_help(&mut self) {
+        self.pending_keys.clear();
+        self.help_scroll = 0;
+        self.push(Help);
     }
 
     pub fn confirm(&mut self) {
@@ -281,36 +934,52 @@ impl App {
             return;
         }
 
-        match self.active_popup {
-            None => {
-                // nothing :)
-            }
-            Reset => {
-                if self.selected_option == SelectedOption::Yes {
-                    self.gamestate = Active;
-                    self.items = generate_data();
-
-                    set_score(0);
-                } else if self.gamestate != Active && self.selected_option == SelectedOption::No {
-                    // TODO: QUIT!
-                }
-                self.selected_option = SelectedOption::default();
-                self.active_popup = None;
-            }
-            PopUp::Config => {
-                match self.tablestate.selected().unwrap() {
-                    0 => self.active_popup = Keymap,
-                    1 => self.active_popup = Colors,
-                    2..=6 => self.option_lock = true,
-                    _ => unimplemented!()
-                }
+        let selected = self.tablestate.selected().unwrap_or(0);
+        (ScreenHandler::of(self.screen()).confirm)(self, selected);
+    }
+
+    fn confirm_menu_entry(&mut self, entry: MenuEntry) {
+        match entry {
+            MenuEntry::Continue => self.continue_game(),
+            MenuEntry::NewGame => self.push(NewGame),
+            MenuEntry::Daily => {
+                self.history = record::read_history();
+                self.push(Screen::Daily);
             }
-            Keymap => {
-                todo!()
+            MenuEntry::Puzzles => self.open_puzzles(),
+            MenuEntry::Sandbox => self.push(Sandbox),
+            MenuEntry::Stats => {
+                self.history = record::read_history();
+                self.push(Stats);
             }
-            Colors => {
-                todo!()
+            MenuEntry::Replays => {
+                self.replays = record::list_replays();
+                self.push(Replays);
             }
+            MenuEntry::Settings => self.push(Screen::Config),
+            MenuEntry::Quit => self.quit = true,
+        }
+    }
+
+    // the rows below the options of a settings screen start something, a submenu option opens its screen
+    fn confirm_option(&mut self, selected: usize) -> Option<Setting> {
+        let setting = self.options().into_iter().nth(selected).map(|option| option.setting);
+        if let Some(Setting::Submenu(screen)) = setting {
+            self.push(screen);
+        }
+        setting
+    }
+
+    fn confirm_reset(&mut self) {
+        let new_game = self.selected_option == SelectedOption::Yes;
+        self.selected_option = SelectedOption::default();
+        if new_game {
+            self.play_again();
+            self.pop();
+        } else if self.gamestate != Active {
+            self.pop_to_menu();
+        } else {
+            self.pop();
         }
     }
 
@@ -320,46 +989,556 @@ impl App {
             return;
         }
 
-        if self.gamestate != Active {
+        (ScreenHandler::of(self.screen()).back)(self);
+    }
+
+    fn close_notice(&mut self) {
+        self.notices.clear();
+        self.pop();
+    }
+
+    // while a replay is watched, the game and its popups only listen to the replay bindings
+    pub fn context(&self) -> Context {
+        match ScreenHandler::of(self.screen()).context {
+            Context::Game | Context::Popup if self.playback.is_some() => Context::Replay,
+            context => context,
+        }
+    }
+
+    // collects key presses until they form one of the bindings of the current context
+    pub fn key_action(&mut self, key: KeyEvent) -> Option<Action> {
+        self.pending_keys.push(KeyBinding::from(key));
+
+        match self.config.keymap.lookup(self.context(), &self.pending_keys) {
+            Lookup::Action(action) => {
+                self.pending_keys.clear();
+                Some(action)
+            }
+            Lookup::Pending => Option::None,
+            Lookup::None => {
+                // a chord that went nowhere shouldn't swallow the key that ended it
+                let last = self.pending_keys.pop().unwrap();
+                let retry = !self.pending_keys.is_empty();
+                self.pending_keys.clear();
+                if retry { self.key_action(KeyEvent::new(last.code, last.modifiers)) } else { Option::None }
+            }
+        }
+    }
+
+    // every action goes through here, replays only listen for the exit, back and help keys
+    pub fn action(&mut self, action: Action) {
+        if self.context() == Context::Replay {
+            match action {
+                Action::Exit => self.quit = true,
+                Action::Back => self.stop_replay(),
+                Action::Help => self.help(),
+                _ => {}
+            }
             return;
         }
 
-        match self.active_popup {
-            None => {
-                // nothing :)
+        match action {
+            Action::Exit => self.quit = true,
+            Action::Help => self.help(),
+            Action::Reset => self.reset(),
+            Action::Confirm => self.confirm(),
+            Action::Back => self.back(),
+            Action::Config => self.config(),
+            Action::Up => self.up(),
+            Action::Down => self.down(),
+            Action::Left => self.left(),
+            Action::Right => self.right(),
+        }
+    }
+
+    pub fn mouse(&mut self, event: MouseEvent) {
+        let mouse = self.config.mouse.clone();
+        let position = Position::new(event.column, event.row);
+
+        match event.kind {
+            MouseEventKind::Down(MouseButton::Left) => {
+                if mouse.drag && self.screen() == Game && self.board_area.contains(position) {
+                    self.drag_start = Some((event.column, event.row));
+                } else if mouse.click && self.popup_area.contains(position) {
+                    self.click(event.row - self.popup_area.y);
+                }
             }
-            Reset => {
-                self.selected_option = SelectedOption::default();
-                self.active_popup = None;
+            MouseEventKind::Up(MouseButton::Left) => {
+                if let Some(start) = self.drag_start.take() {
+                    match drag_direction(start, (event.column, event.row), mouse.drag_distance) {
+                        Some(Up) => self.up(),
+                        Some(Down) => self.down(),
+                        Some(Left) => self.left(),
+                        Some(Right) => self.right(),
+                        Option::None => {}
+                    }
+                }
             }
-            PopUp::Config => self.active_popup = None,
-            Keymap => self.active_popup = PopUp::Config,
-            Colors => self.active_popup = PopUp::Config,
+            MouseEventKind::ScrollUp if mouse.scroll && self.screen() != Game => self.up(),
+            MouseEventKind::ScrollDown if mouse.scroll && self.screen() != Game => self.down(),
+            _ => {}
         }
     }
-}
 
-pub fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> io::Result<()> {
-    let keymap = app.config.keymap.clone();
+    // handles a click on the given line of the current screen, counted from its top border
+    fn click(&mut self, line: u16) {
+        (ScreenHandler::of(self.screen()).click)(self, line);
+    }
+
+    // selects the clicked row, and confirms it right away on screens where that doesn't change anything by accident
+    fn click_row(&mut self, line: u16, confirm: bool) {
+        let row = (line as usize).saturating_sub(1) + self.tablestate.offset();
+        if line == 0 || row >= self.rows() {
+            return;
+        }
+
+        if self.tablestate.selected() != Some(row) {
+            self.option_lock = false;
+            self.tablestate.select(Some(row));
+        }
+        if confirm {
+            self.confirm();
+        }
+    }
+
+    fn click_reset(&mut self, line: u16) {
+        // the options are below the two message lines and an empty line
+        self.selected_option = match line {
+            4 => SelectedOption::Yes,
+            5 => SelectedOption::No,
+            _ => return,
+        };
+        self.confirm();
+    }
+}
 
+pub fn run_app<B: Backend + io::Write>(terminal: &mut Terminal<B>, app: &mut App) -> io::Result<()> {
     loop {
-        terminal.draw(|f| ui(f, &mut app))?;
-
-        if let Event::Key(key) = event::read()? {
-            if key.kind == KeyEventKind::Press {
-                match key.code {
-                    code if keymap.exit.contains(&code) => return Ok(()),
-                    code if keymap.reset.contains(&code) => app.reset(),
-                    code if keymap.confirm.contains(&code) => app.confirm(),
-                    code if keymap.back.contains(&code) => app.back(),
-                    code if keymap.config.contains(&code) => app.config(),
-                    code if keymap.up.contains(&code) => app.up(),
-                    code if keymap.down.contains(&code) => app.down(),
-                    code if keymap.left.contains(&code) => app.left(),
-                    code if keymap.right.contains(&code) => app.right(),
-                    _ => {}
+        let mouse_capture = app.config.mouse.any_enabled();
+        if app.reload_config() && mouse_capture != app.config.mouse.any_enabled() {
+            if mouse_capture {
+                execute!(terminal.backend_mut(), DisableMouseCapture)?;
+            } else {
+                execute!(terminal.backend_mut(), EnableMouseCapture)?;
+            }
+        }
+
+        app.tick();
+        terminal.draw(|f| ui(f, app))?;
+
+        // replays advance on their own, countdowns have to be redrawn and everything else only has to wake up to look for config changes
+        let timeout = if app.playback.is_some() {
+            REPLAY_STEP
+        } else if app.record.time_limit.is_some() {
+            TICK
+        } else {
+            CONFIG_POLL
+        };
+        if !event::poll(timeout)? {
+            app.step_replay();
+            continue;
+        }
+        match event::read()? {
+            Event::Key(key) if key.kind == KeyEventKind::Press => {
+                if let Some(action) = app.key_action(key) {
+                    app.action(action);
                 }
             }
+            Event::Mouse(mouse) if app.playback.is_none() => app.mouse(mouse),
+            Event::FocusLost => app.focused = false,
+            Event::FocusGained => app.focused = true,
+            _ => {}
+        }
+        app.tick();
+        if app.quit {
+            return Ok(());
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod record_test {
+    use std::time::Duration;
+
+    use itertools::Itertools;
+
+    use crate::{Config, Data, record};
+    use crate::movement::BLOCKER;
+    use crate::puzzle::{Goal, Puzzle, Spawn};
+    use crate::record::{BLOCKER_COUNTS, MOVE_LIMITS, TIME_LIMITS, Variant};
+    use crate::Direction::*;
+    use crate::Screen;
+    use crate::util::{SpawnPosition, SpawnRules, tile_count};
+    use super::App;
+
+    #[test]
+    fn test_load_reproduces_game() {
+        let mut app = App::new(Config::default(), Some(42));
+        for dir in [Left, Up, Right, Down, Left, Left, Up] {
+            app.shift(dir);
+        }
+
+        let loaded = App::load(Config::default(), app.record.clone());
+        assert_eq!(app.items, loaded.items);
+        assert_eq!(app.record.moves, loaded.record.moves);
+    }
+
+    #[test]
+    fn test_load_keeps_time_played() {
+        let mut app = App::new(Config::default(), Some(42));
+        app.shift(Left);
+        let mut save = app.saved_record();
+        save.elapsed = Duration::from_secs(90);
+
+        let loaded = App::load(Config::default(), save);
+        assert_eq!(Duration::from_secs(90), loaded.timer.elapsed());
+    }
+
+    #[test]
+    fn test_blitz_counts_down() {
+        let mut app = App::new(Config::default(), Some(42));
+        app.variant = Variant::Blitz;
+        app.new_game(Some(42));
+        let mut save = app.saved_record();
+        save.elapsed = Duration::from_secs(100);
+
+        let loaded = App::load(Config::default(), save);
+        assert_eq!(Variant::Blitz, loaded.record.variant);
+        assert_eq!(Some(TIME_LIMITS[0] - Duration::from_secs(100)), loaded.time_left());
+    }
+
+    #[test]
+    fn test_play_again_keeps_the_rules() {
+        let mut app = App::new(Config::default(), Some(42));
+        app.variant = Variant::Blitz;
+        app.time_limit = 1;
+        app.new_game(Some(42));
+
+        let mut loaded = App::load(Config::default(), app.saved_record());
+        assert_eq!(Variant::Classic, loaded.variant);
+        loaded.play_again();
+        assert_eq!(Variant::Blitz, loaded.record.variant);
+        assert_eq!(Some(TIME_LIMITS[1]), loaded.record.time_limit);
+        assert_ne!(42, loaded.record.seed);
+    }
+
+    #[test]
+    fn test_play_again_practices_the_daily() {
+        let mut app = App::new(Config::default(), Some(42));
+        app.record.variant = Variant::Daily;
+        app.record.date = Some(record::today());
+        app.play_again();
+        assert_eq!(Variant::Daily, app.record.variant);
+        assert_eq!(None, app.record.date);
+        assert_eq!(record::daily_seed(&record::today()), app.record.seed);
+    }
+
+    #[test]
+    fn test_limited_moves_count_down() {
+        let mut app = App::new(Config::default(), Some(42));
+        app.variant = Variant::LimitedMoves;
+        app.new_game(Some(42));
+        for dir in [Left, Up, Right] {
+            app.shift(dir);
+        }
+
+        let loaded = App::load(Config::default(), app.saved_record());
+        assert_eq!(Some(MOVE_LIMITS[1] - loaded.record.moves.len()), loaded.moves_left());
+        assert_eq!(None, loaded.time_left());
+    }
+
+    #[test]
+    fn test_blockers_stay() {
+        let mut app = App::new(Config::default(), Some(42));
+        app.variant = Variant::Blockers;
+        app.new_game(Some(42));
+        let blockers = |app: &App| app.items.iter().flat_map(|row| row.numbers.iter()).positions(|v| *v == BLOCKER).collect::<Vec<_>>();
+        let before = blockers(&app);
+        assert_eq!(BLOCKER_COUNTS[1], before.len());
+
+        for dir in [Left, Up, Right, Down, Left] {
+            app.shift(dir);
+        }
+        assert_eq!(before, blockers(&app));
+        assert_eq!(2 + app.record.moves.len() - app.merges, tile_count(&app.items));
+
+        let loaded = App::load(Config::default(), app.saved_record());
+        assert_eq!(app.items, loaded.items);
+    }
+
+    #[test]
+    fn test_fibonacci_game() {
+        let mut app = App::new(Config::default(), Some(42));
+        app.variant = Variant::Fibonacci;
+        app.new_game(Some(42));
+        assert_eq!(1597, app.record.win_value);
+        for dir in [Left, Up, Right, Down, Left, Up] {
+            app.shift(dir);
+        }
+        // only 1s and 2s spawn, everything else has to come from merges
+        assert!(app.items.iter().flat_map(|row| row.numbers.iter()).all(|v| [0, 1, 2, 3, 5, 8, 13].contains(v)));
+
+        let loaded = App::load(Config::default(), app.saved_record());
+        assert_eq!(app.items, loaded.items);
+        assert_eq!(1597, loaded.record.win_value);
+    }
+
+    #[test]
+    fn test_triples_game() {
+        let mut app = App::new(Config::default(), Some(42));
+        app.variant = Variant::Triples;
+        app.triple_spawns = 1;
+        app.new_game(Some(42));
+        assert_eq!(2187, app.record.win_value);
+        for dir in [Left, Up, Right, Down, Left, Up] {
+            app.shift(dir);
+        }
+        // only 3s spawn, so every tile is a power of three
+        assert!(app.items.iter().flat_map(|row| row.numbers.iter()).all(|v| [0, 3, 9, 27].contains(v)));
+
+        let loaded = App::load(Config::default(), app.saved_record());
+        assert_eq!(app.items, loaded.items);
+        assert_eq!(Some(SpawnRules::default().with_values((3, 3))), loaded.record.spawns);
+    }
+
+    #[test]
+    fn test_triples_merges_count_once() {
+        let mut app = App::new(Config::default(), Some(42));
+        app.variant = Variant::Triples;
+        app.new_game(Some(42));
+        app.items = vec![
+            Data { numbers: vec![3, 3, 3, 0] },
+            Data { numbers: vec![9, 9, 9, 0] },
+            Data { numbers: vec![0, 0, 0, 0] },
+            Data { numbers: vec![0, 0, 0, 0] },
+        ];
+        app.shift(Left);
+        assert_eq!(2, app.merges);
+    }
+
+    #[test]
+    fn test_spawn_rules_of_the_config() {
+        let mut config = Config::default();
+        config.spawns.initial = 3;
+        config.spawns.per_move = 2;
+        config.spawns.position = SpawnPosition::Corners;
+        let mut app = App::new(config, Some(42));
+        app.new_game(Some(42));
+        assert_eq!(3, tile_count(&app.items));
+        for dir in [Left, Up, Right] {
+            app.shift(dir);
+        }
+        assert_eq!(3 + 2 * app.record.moves.len() - app.merges, tile_count(&app.items));
+
+        // the rules are part of the record, so the game doesn't depend on the config it is loaded with
+        let loaded = App::load(Config::default(), app.saved_record());
+        assert_eq!(app.items, loaded.items);
+    }
+
+    #[test]
+    fn test_adversarial_game() {
+        let mut config = Config::default();
+        config.spawns.position = SpawnPosition::Adversarial;
+        let mut app = App::new(config, Some(42));
+        app.new_game(Some(42));
+        for dir in [Left, Up, Right, Down, Left, Up, Right] {
+            app.shift(dir);
+        }
+        assert_eq!(2 + app.record.moves.len() - app.merges, tile_count(&app.items));
+
+        let loaded = App::load(Config::default(), app.saved_record());
+        assert_eq!(app.items, loaded.items);
+    }
+
+    #[test]
+    fn test_load_puzzle() {
+        let puzzle = Puzzle {
+            name: String::from("Test"),
+            pack: String::from("Test"),
+            board: vec![vec![2, 4, 8], vec![0, 0, 0], vec![0, 0, 0]],
+            spawns: Some(vec![Spawn { row: 0, column: 0, value: 2 }]),
+            goal: Goal::Reach { tile: 64 },
+            moves: Some(5),
+        };
+        let mut app = App::new(Config::default(), Some(42));
+        app.start_puzzle(puzzle);
+        app.shift(Down);
+        app.shift(Up);
+        assert_eq!(vec![4, 4, 8], app.items[0].numbers);
+        assert_eq!(vec![0, 0, 0], app.items[2].numbers);
+
+        let loaded = App::load(Config::default(), app.saved_record());
+        assert_eq!(app.items, loaded.items);
+        assert_eq!(Some(3), loaded.moves_left());
+    }
+
+    #[test]
+    fn test_sandbox_without_spawns() {
+        let mut app = App::new(Config::default(), Some(42));
+        app.push(Screen::Sandbox);
+        app.push(Screen::Editor);
+        app.confirm();
+        app.up();
+        app.up();
+        app.confirm();
+        app.right();
+        app.confirm();
+        app.right();
+        assert_eq!(vec![4, 2, 0, 0], app.editor[0].numbers);
+
+        app.sandbox_spawns = false;
+        app.start_sandbox();
+        app.shift(Right);
+        assert_eq!(vec![0, 0, 4, 2], app.items[0].numbers);
+        assert_eq!(2, tile_count(&app.items));
+
+        let loaded = App::load(Config::default(), app.saved_record());
+        assert_eq!(app.items, loaded.items);
+    }
+}
+
+#[cfg(test)]
+mod screen_test {
+    use crate::{Config, Screen};
+    use crate::keymap::Context;
+    use super::App;
+
+    #[test]
+    fn test_help_returns_to_previous_screen() {
+        let mut app = App::new(Config::default(), Some(1));
+        app.push(Screen::Game);
+        app.config();
+        app.help();
+        assert_eq!(Screen::Help, app.screen());
+
+        app.down();
+        app.back();
+        assert_eq!(Screen::Config, app.screen());
+        app.help();
+        app.help();
+        assert_eq!(Screen::Config, app.screen());
+    }
+
+    #[test]
+    fn test_back_restores_selection() {
+        let mut app = App::new(Config::default(), Some(1));
+        app.config();
+        app.down();
+        app.down();
+        app.push(Screen::Help);
+        assert_eq!(Some(0), app.tablestate.selected());
+
+        app.back();
+        assert_eq!(Some(2), app.tablestate.selected());
+        app.back();
+        app.back();
+        assert_eq!(Screen::MainMenu, app.screen());
+        assert_eq!(1, app.screens.len());
+    }
+
+    #[test]
+    fn test_replay_context() {
+        let mut app = App::new(Config::default(), Some(1));
+        app.start_replay(app.record.clone());
+        app.push(Screen::Game);
+        assert_eq!(Context::Replay, app.context());
+        app.help();
+        assert_eq!(Context::Menu, app.context());
+    }
+
+    #[test]
+    fn test_option_lock_keeps_the_row() {
+        let mut app = App::new(Config::default(), Some(1));
+        app.config();
+        app.down();
+        app.confirm();
+        assert!(app.option_lock);
+        app.down();
+        assert_eq!(Some(1), app.tablestate.selected());
+        app.back();
+        assert!(!app.option_lock);
+        assert_eq!(Screen::Config, app.screen());
+    }
+
+    #[test]
+    fn test_reset_and_config_per_screen() {
+        let mut app = App::new(Config { reset_popup: true, ..Config::default() }, Some(1));
+        app.push(Screen::Game);
+        app.reset();
+        assert_eq!(Screen::Reset, app.screen());
+        app.reset();
+        assert_eq!(Screen::Game, app.screen());
+
+        app.config();
+        assert_eq!(Screen::Config, app.screen());
+        // only opened from the game and the main menu
+        app.reset();
+        app.help();
+        app.config();
+        assert_eq!(Screen::Help, app.screen());
+        app.help();
+        app.config();
+        assert_eq!(Screen::Game, app.screen());
+    }
+}
+
+#[cfg(test)]
+mod reload_test {
+    use std::fs;
+    use std::path::PathBuf;
+    use std::time::{Duration, SystemTime};
+
+    use crate::{App, Screen};
+    use crate::cli::Overrides;
+    use crate::colors::Theme;
+    use crate::settings::{CONFIG_VERSION, ConfigWatcher};
+
+    fn app(name: &str, overrides: Overrides) -> (App, PathBuf) {
+        let path = std::env::temp_dir().join(format!("tui-2048-{}-{name}", std::process::id()));
+        fs::write(&path, format!(r#"{{"version":{CONFIG_VERSION},"theme":"Classic","field_size":1}}"#)).unwrap();
+        let mut watcher = ConfigWatcher::new(path.clone(), overrides);
+        let mut app = App::new(watcher.load().0, Some(1));
+        app.watcher = Some(watcher);
+        (app, path)
+    }
+
+    // an edit in another program, the modification time is moved so it is noticed within the same second
+    fn edit(path: &PathBuf, contents: String) {
+        fs::write(path, contents).unwrap();
+        let file = fs::File::options().write(true).open(path).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(10)).unwrap();
+    }
+
+    #[test]
+    fn test_reload_keeps_new_game_choices() {
+        let (mut app, path) = app("reload-new-game.json", Overrides::default());
+        app.push(Screen::NewGame);
+        let row = app.options().iter().position(|option| option.key == Some("field_size")).unwrap();
+        app.tablestate.select(Some(row));
+        app.right();
+        assert_eq!(2, app.config.field_size);
+
+        edit(&path, format!(r#"{{"version":{CONFIG_VERSION},"theme":"Rainbow","field_size":1}}"#));
+        assert!(app.reload_config());
+        fs::remove_file(&path).unwrap();
+        assert_eq!(2, app.config.field_size);
+        assert_eq!(Theme::Rainbow, app.config.theme);
+    }
+
+    #[test]
+    fn test_reload_keeps_overridden_changes() {
+        let (mut app, path) = app("reload-override.json", Overrides { theme: Some(Theme::Tritanopia), ..Overrides::default() });
+        assert_eq!(Theme::Tritanopia, app.config.theme);
+        app.push(Screen::Colors);
+        let row = Theme::ALL.iter().position(|theme| *theme == Theme::Rainbow).unwrap();
+        app.tablestate.select(Some(row));
+        app.confirm();
+        assert_eq!(Theme::Rainbow, app.config.theme);
+
+        edit(&path, format!(r#"{{"version":{CONFIG_VERSION},"theme":"Rainbow","field_size":2}}"#));
+        assert!(app.reload_config());
+        fs::remove_file(&path).unwrap();
+        assert_eq!(Theme::Rainbow, app.config.theme);
+        assert_eq!(2, app.config.field_size);
+    }
+}
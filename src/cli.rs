@@ -0,0 +1,148 @@
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand};
+
+use crate::colors::Theme;
+use crate::{Config, FIELD_SIZES, WIN_VALUES};
+
+#[derive(Parser)]
+#[command(version, about = "The popular game 2048 in your terminal")]
+pub struct Cli {
+    /// Config file to use instead of $XDG_CONFIG_HOME/tui-2048/config.json
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub overrides: Overrides,
+
+    /// Seed for the tile spawns of the first game
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Continue a saved game
+    #[arg(long, value_name = "SAVE", conflicts_with = "replay")]
+    pub load: Option<PathBuf>,
+
+    /// Watch the replay of a finished game
+    #[arg(long, value_name = "FILE")]
+    pub replay: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+// options that take precedence over the config file, they are applied again whenever the config is reloaded
+#[derive(Args, Clone, Default)]
+pub struct Overrides {
+    /// Board size, only square boards from 3x3 to 9x9 are supported
+    #[arg(long, value_name = "WxH", value_parser = parse_size)]
+    pub size: Option<usize>,
+
+    /// Tile value needed to win
+    #[arg(long, value_name = "VALUE", value_parser = parse_win)]
+    pub win: Option<usize>,
+
+    /// Color theme of the tiles
+    #[arg(long, value_enum)]
+    pub theme: Option<Theme>,
+
+    /// Disable the win/loss animation for this session
+    #[arg(long)]
+    pub no_animations: bool,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Print statistics of all finished games
+    Stats,
+    /// Overwrite the config file with the default config
+    ResetConfig,
+    /// Print the default config to stdout
+    PrintDefaultConfig,
+}
+
+impl Overrides {
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(size) = self.size {
+            config.field_size = size;
+        }
+        if let Some(win) = self.win {
+            config.win_value = win;
+        }
+        if let Some(theme) = self.theme {
+            config.theme = theme;
+        }
+        if self.no_animations {
+            config.ending_animation = false;
+        }
+    }
+
+    // the config keys that are set on the command line
+    pub fn keys(&self) -> Vec<&'static str> {
+        [(self.size.is_some(), "field_size"), (self.win.is_some(), "win_value"), (self.theme.is_some(), "theme"), (self.no_animations, "ending_animation")]
+            .into_iter()
+            .filter(|(set, _)| *set)
+            .map(|(_, key)| key)
+            .collect()
+    }
+}
+
+// returns the index of the size in FIELD_SIZES
+fn parse_size(s: &str) -> Result<usize, String> {
+    let (width, height) = s.split_once(['x', 'X']).unwrap_or((s, s));
+    let width = width.trim().parse::<u16>().map_err(|e| format!("invalid width: {e}"))?;
+    let height = height.trim().parse::<u16>().map_err(|e| format!("invalid height: {e}"))?;
+
+    if width != height {
+        return Err(String::from("only square boards are supported"));
+    }
+    FIELD_SIZES.iter().position(|size| *size == width)
+        .ok_or(format!("size has to be between {0}x{0} and {1}x{1}", FIELD_SIZES[0], FIELD_SIZES[FIELD_SIZES.len() - 1]))
+}
+
+// returns the index of the value in WIN_VALUES
+fn parse_win(s: &str) -> Result<usize, String> {
+    let value = s.parse::<u32>().map_err(|e| e.to_string())?;
+    WIN_VALUES.iter().position(|v| *v == value)
+        .ok_or(format!("win value has to be one of {}", WIN_VALUES.map(|v| v.to_string()).join(", ")))
+}
+
+#[cfg(test)]
+mod parse_test {
+    use clap::Parser;
+
+    use super::{Cli, parse_size, parse_win};
+    use crate::Config;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(Ok(1), parse_size("4x4"));
+        assert_eq!(Ok(0), parse_size("3X3"));
+        assert_eq!(Ok(6), parse_size("9"));
+        assert!(parse_size("4x5").is_err());
+        assert!(parse_size("10x10").is_err());
+        assert!(parse_size("ax4").is_err());
+    }
+
+    #[test]
+    fn test_parse_win() {
+        assert_eq!(Ok(8), parse_win("2048"));
+        assert_eq!(Ok(0), parse_win("8"));
+        assert!(parse_win("100").is_err());
+        assert!(parse_win("-2").is_err());
+    }
+
+    #[test]
+    fn test_parse_no_animations() {
+        let cli = Cli::try_parse_from(["tui-2048", "--no-animations"]).unwrap();
+        let mut config = Config::default();
+        assert!(config.ending_animation);
+        cli.overrides.apply(&mut config);
+        assert!(!config.ending_animation);
+
+        let cli = Cli::try_parse_from(["tui-2048"]).unwrap();
+        let mut config = Config::default();
+        cli.overrides.apply(&mut config);
+        assert!(config.ending_animation);
+    }
+}
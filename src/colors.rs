@@ -1,10 +1,10 @@
-use std::collections::HashMap;
-use config::Value;
+use clap::ValueEnum;
 use ratatui::prelude::{Color, Line, Stylize};
-use ratatui::prelude::Color::{Black, Blue, Gray, Green};
+use ratatui::prelude::Color::{Black, Blue, DarkGray, Gray, Green};
 use ratatui::style::Color::White;
 use serde::{Deserialize, Serialize};
-use crate::util::deserialize_color;
+use crate::movement::{BLOCKER, MergeRule};
+use crate::util::ConfigTable;
 
 #[derive(Deserialize, Serialize)]
 pub struct TableColors {
@@ -30,35 +30,80 @@ impl TableColors {
         }
     }
 
-    pub fn from_map(map: HashMap<String, Value>) -> Self {
+    pub fn from_map(mut map: ConfigTable) -> Self {
+        let default = Self::default();
         Self {
-            buffer_bg: deserialize_color(&map, "buffer_bg"),
-            header_bg: deserialize_color(&map, "header_bg"),
-            header_fg: deserialize_color(&map, "header_fg"),
-            row_fg: deserialize_color(&map, "row_fg"),
-            selected_style_fg: deserialize_color(&map, "selected_style_fg"),
-            normal_row_color: deserialize_color(&map, "normal_row_color"),
-            footer_border_color: deserialize_color(&map, "footer_border_color"),
+            buffer_bg: map.get("buffer_bg", default.buffer_bg),
+            header_bg: map.get("header_bg", default.header_bg),
+            header_fg: map.get("header_fg", default.header_fg),
+            row_fg: map.get("row_fg", default.row_fg),
+            selected_style_fg: map.get("selected_style_fg", default.selected_style_fg),
+            normal_row_color: map.get("normal_row_color", default.normal_row_color),
+            footer_border_color: map.get("footer_border_color", default.footer_border_color),
         }
     }
 }
 
-// TODO: maybe make this procedural?
-pub fn value_bg_color(value: u32) -> Color {
-    match value {
-        2 => Color::Rgb(20, 20, 20),
-        4 => Color::Rgb(40, 25, 25),
-        8 => Color::Rgb(80, 30, 30),
-        16 => Color::Rgb(120, 35, 35),
-        32 => Color::Rgb(160, 40, 40),
-        64 => Color::Rgb(200, 40, 40),
-        128 => Color::Rgb(140, 140, 40),
-        256 => Color::Rgb(180, 180, 40),
-        512 => Color::Rgb(210, 190, 40),
-        1024 => Color::Rgb(240, 200, 40),
-        2048 => Color::Rgb(255, 200, 40),
-        _ => Black,
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize, ValueEnum)]
+pub enum Theme {
+    #[default]
+    Classic,
+    Rainbow,
+    Deuteranopia,
+    Protanopia,
+    Tritanopia,
+}
+
+impl Theme {
+    pub const ALL: [Theme; 5] = [Theme::Classic, Theme::Rainbow, Theme::Deuteranopia, Theme::Protanopia, Theme::Tritanopia];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Theme::Classic => "Classic",
+            Theme::Rainbow => "Rainbow",
+            Theme::Deuteranopia => "Deuteranopia",
+            Theme::Protanopia => "Protanopia",
+            Theme::Tritanopia => "Tritanopia",
+        }
+    }
+
+    // color stops from the lowest (2) to the highest (2048) tile
+    pub fn stops(&self) -> &'static [&'static str] {
+        match self {
+            Theme::Classic => &["#141414", "#281919", "#501E1E", "#782323", "#A02828", "#C82828", "#8C8C28", "#B4B428", "#D2BE28", "#F0C828", "#FFC828"],
+            Theme::Rainbow => &["#C82828", "#E67E22", "#F1C40F", "#27AE60", "#2980B9", "#8E44AD"],
+            Theme::Deuteranopia | Theme::Protanopia => &["#1E1E32", "#0072B2", "#56B4E9", "#E69F00", "#F0E442"],
+            Theme::Tritanopia => &["#1E1E1E", "#009E73", "#CC79A7", "#D55E00", "#F5F5F5"],
+        }
+    }
+}
+
+pub fn value_bg_color(value: u32, theme: Theme, rule: MergeRule) -> Color {
+    if value == 0 {
+        return Black;
+    }
+    if value == BLOCKER {
+        return DarkGray;
     }
+
+    // tiles are spread evenly over the stops by how many merges they are away from the smallest tile,
+    // everything above the default win value gets the last color
+    let stops = theme.stops();
+    let last = (stops.len() - 1) as f32;
+    let position = rule.progress(value) * last;
+    let (low, high) = (hex_to_rgb(stops[position.floor() as usize]), hex_to_rgb(stops[position.ceil() as usize]));
+    let ratio = position.fract();
+
+    let mix = |low: u8, high: u8| (low as f32 + (high as f32 - low as f32) * ratio).round() as u8;
+    Color::Rgb(mix(low.0, high.0), mix(low.1, high.1), mix(low.2, high.2))
+}
+
+fn hex_to_rgb(hex: &str) -> (u8, u8, u8) {
+    (
+        u8::from_str_radix(hex.get(1..=2).unwrap(), 16).unwrap(),
+        u8::from_str_radix(hex.get(3..=4).unwrap(), 16).unwrap(),
+        u8::from_str_radix(hex.get(5..=6).unwrap(), 16).unwrap(),
+    )
 }
 
 pub fn generate_color_bar<'a>(width: u16, colors: &'a [&str]) -> Line<'a> {
@@ -67,21 +112,12 @@ pub fn generate_color_bar<'a>(width: u16, colors: &'a [&str]) -> Line<'a> {
     for x in 0..width {
         let mut color = 0u32;
         let step_size = width / (colors.len() - 1) as u16;
-        let mut index = (x / step_size) as usize;
-
-        if index == colors.len() {
-            index -= 1;
-        }
+        let index = ((x / step_size) as usize).min(colors.len() - 2);
         let low = colors[index];
         let high = colors[index + 1];
 
-        let red_low = u8::from_str_radix(low.get(1..=2).unwrap(), 16).unwrap();
-        let green_low = u8::from_str_radix(low.get(3..=4).unwrap(), 16).unwrap();
-        let blue_low = u8::from_str_radix(low.get(5..=6).unwrap(), 16).unwrap();
-
-        let red_high = u8::from_str_radix(high.get(1..=2).unwrap(), 16).unwrap();
-        let green_high = u8::from_str_radix(high.get(3..=4).unwrap(), 16).unwrap();
-        let blue_high = u8::from_str_radix(high.get(5..=6).unwrap(), 16).unwrap();
+        let (red_low, green_low, blue_low) = hex_to_rgb(low);
+        let (red_high, green_high, blue_high) = hex_to_rgb(high);
 
         color += (red_high as f32 * ((x % step_size) as f32 / step_size as f32)).floor() as u32;
         color += (red_low as f32 * ((step_size - (x % step_size)) as f32 / step_size as f32)).floor() as u32;
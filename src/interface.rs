@@ -1,6 +1,5 @@
 use std::fmt::Display;
 use std::rc::Rc;
-use crossterm::event::KeyCode;
 
 use itertools::Itertools;
 use ratatui::Frame;
@@ -8,11 +7,15 @@ use ratatui::layout::{Alignment, Constraint, Direction, Layout, Rect};
 use ratatui::prelude::{Line, Style, Text};
 use ratatui::style::{Color, Modifier, Stylize};
 use ratatui::text::Span;
-use ratatui::widgets::{Block, Borders, BorderType, Cell, Paragraph, Row, Table};
+use ratatui::widgets::{Bar, BarChart, BarGroup, Block, Borders, BorderType, Cell, Paragraph, Row, Sparkline, Table, Wrap};
 
-use crate::{App, FIELD_SIZES, GameState, get_highscore, get_score, PopUp, SelectedOption, WIN_VALUES};
-use crate::colors::{generate_color_bar, TableColors, value_bg_color};
-use crate::util::{format_keycode, INFO_TEXT};
+use crate::{App, Data, Direction as Move, GameState, get_highscore, get_score, record, Screen, SelectedOption};
+use crate::colors::{generate_color_bar, Theme, value_bg_color};
+use crate::keymap::{Action, Chord, Context, KEYMAP_ROWS};
+use crate::menu::{MenuOption, Setting};
+use crate::movement::{BLOCKER, MergeRule};
+use crate::record::{Stats, Variant};
+use crate::util::{control_info, format_duration, largest_gain, max_tile};
 
 pub fn ui(f: &mut Frame, app: &mut App) {
     let rects = Layout::new(
@@ -20,8 +23,7 @@ pub fn ui(f: &mut Frame, app: &mut App) {
         [Constraint::Length(5), Constraint::Min(15), if app.config.control_info { Constraint::Length(5) } else { Constraint::Length(0) }]
     ).split(f.size());
 
-    app.config.colors = TableColors::default();
-    render_title(f, rects[0]);
+    render_title(f, app, rects[0]);
 
     let mut config_highlight = Style::default().add_modifier(Modifier::REVERSED).fg(Color::LightCyan);
     if app.option_lock {
@@ -29,12 +31,22 @@ pub fn ui(f: &mut Frame, app: &mut App) {
         config_highlight = config_highlight.add_modifier(Modifier::SLOW_BLINK);
     }
 
-    match app.active_popup {
-        PopUp::Reset => render_reset(f, app, rects.clone(), app.gamestate.clone()),
-        PopUp::Config => render_config(f, app, rects.clone(), config_highlight),
-        PopUp::Keymap => render_keymap(f, app, rects.clone(), config_highlight),
-        PopUp::Colors => render_colors(f, app, rects.clone(), config_highlight),
-        PopUp::None => render_game(f, app, rects[1])
+    match app.screen() {
+        Screen::MainMenu => render_main_menu(f, app, rects.clone(), config_highlight),
+        Screen::NewGame => render_setup(f, app, rects.clone(), config_highlight, "New Game"),
+        Screen::Daily => render_daily(f, app, rects.clone(), config_highlight),
+        Screen::Puzzles => render_puzzles(f, app, rects.clone(), config_highlight),
+        Screen::Sandbox => render_setup(f, app, rects.clone(), config_highlight, "Sandbox"),
+        Screen::Editor => render_editor(f, app, rects[1], config_highlight),
+        Screen::Reset => render_reset(f, app, rects.clone(), app.gamestate.clone()),
+        Screen::Config => render_config(f, app, rects.clone(), config_highlight),
+        Screen::Keymap => render_keymap(f, app, rects.clone(), config_highlight),
+        Screen::Colors => render_colors(f, app, rects.clone(), config_highlight),
+        Screen::Notice => render_notice(f, app, rects.clone()),
+        Screen::Help => render_help(f, app, rects.clone()),
+        Screen::Stats => render_stats(f, app, rects.clone(), config_highlight),
+        Screen::Replays => render_replays(f, app, rects.clone(), config_highlight),
+        Screen::Game => render_game(f, app, rects[1])
     }
 
     if app.config.control_info {
@@ -42,19 +54,41 @@ pub fn ui(f: &mut Frame, app: &mut App) {
     }
 }
 
-fn render_title(f: &mut Frame, area: Rect) {
+fn render_title(f: &mut Frame, app: &App, area: Rect) {
     let score_string = &get_score().to_string();
     let highscore_string = &get_highscore().to_string();
+    let moves = app.record.moves.len();
+    let per_move = if moves == 0 { 0.0 } else { get_score() as f64 / moves as f64 };
 
     let lines = vec![
-        Line::from(Span::styled("2048", Style::default().fg(Color::LightYellow))),
+        Line::from(Span::styled(
+            match (&app.record.puzzle, app.playback.is_some()) {
+                (_, true) => String::from("2048 (Replay)"),
+                (Some(puzzle), false) => format!("2048 - {}: {}", puzzle.name, puzzle.describe()),
+                (None, false) => String::from("2048"),
+            },
+            Style::default().fg(Color::LightYellow),
+        )),
         Line::from(vec![
             Span::styled("Score: ", Style::default().fg(Color::Yellow)),
             Span::styled(score_string, Style::default().fg(Color::LightCyan)),
+            Span::styled(if app.moves_left().is_some() { " | Moves Left: " } else { " | Moves: " }, Style::default().fg(Color::Yellow)),
+            match app.moves_left() {
+                // the last moves stand out
+                Some(left) => Span::styled(left.to_string(), Style::default()
+                    .fg(if left <= 10 { Color::LightRed } else { Color::LightCyan })
+                    .add_modifier(Modifier::BOLD)),
+                None => Span::styled(moves.to_string(), Style::default().fg(Color::LightCyan)),
+            },
         ]),
         Line::from(vec![
             Span::styled("Highscore: ", Style::default().fg(Color::Yellow)),
             Span::styled(highscore_string, Style::default().fg(Color::LightCyan)),
+            // blitz games count down
+            Span::styled(if app.record.time_limit.is_some() { " | Time Left: " } else { " | Time: " }, Style::default().fg(Color::Yellow)),
+            Span::styled(format_duration(app.time_left().unwrap_or(app.timer.elapsed())), Style::default().fg(Color::LightCyan)),
+            Span::styled(" | Per Move: ", Style::default().fg(Color::Yellow)),
+            Span::styled(format!("{per_move:.1}"), Style::default().fg(Color::LightCyan)),
         ]),
     ];
 
@@ -71,18 +105,40 @@ fn render_title(f: &mut Frame, area: Rect) {
 }
 
 fn render_game(f: &mut Frame, app: &mut App, area: Rect) {
-    let square_size = area.height / FIELD_SIZES[app.config.field_size];
+    let items = app.items.clone();
+    app.board_area = render_board(f, app, area, &items, app.record.variant.merge_rule(), None);
+    app.popup_area = Rect::default();
+}
+
+// the field under the cursor gets the highlight style
+fn render_editor(f: &mut Frame, app: &mut App, area: Rect, config_highlight: Style) {
+    let editor = app.editor.clone();
+    render_board(f, app, area, &editor, MergeRule::Doubling, Some((app.cursor, config_highlight)));
+    app.board_area = Rect::default();
+    app.popup_area = Rect::default();
+}
 
-    let rows = app.items.iter().map(|data| {
+// returns the area of the board, which depends on its size
+fn render_board(f: &mut Frame, app: &mut App, area: Rect, field: &[Data], rule: MergeRule, cursor: Option<((usize, usize), Style)>) -> Rect {
+    let size = field.len() as u16;
+    let square_size = area.height / size;
+
+    let rows = field.iter().enumerate().map(|(y, data)| {
         let items = data.numbers();
         Row::new(
-            items.iter().map(|i| Cell::from(
-                [
-                    vec![Line::from(""); (square_size / 2) as usize],
-                    vec![Line::from(format!("{i}")).alignment(Alignment::Center)],
-                    vec![Line::from(""); (square_size / 2 - 1) as usize],
-                ].concat()
-            ).bg(value_bg_color(*i))).collect_vec()
+            items.iter().enumerate().map(|(x, i)| {
+                let cell = Cell::from(
+                    [
+                        vec![Line::from(""); (square_size / 2) as usize],
+                        vec![Line::from(if *i == BLOCKER { String::from("▓▓▓▓") } else { format!("{i}") }).alignment(Alignment::Center)],
+                        vec![Line::from(""); (square_size / 2).saturating_sub(1) as usize],
+                    ].concat()
+                ).bg(value_bg_color(*i, app.config.theme, rule));
+                match cursor {
+                    Some((position, highlight)) if position == (y, x) => cell.style(highlight),
+                    _ => cell,
+                }
+            }).collect_vec()
         )
             .style(Style::new()
                 .fg(app.config.colors.row_fg)
@@ -91,7 +147,7 @@ fn render_game(f: &mut Frame, app: &mut App, area: Rect) {
     });
 
     let width_constraint = Constraint::Length(square_size * 2);
-    let t = Table::new(rows, [width_constraint, width_constraint, width_constraint, width_constraint])
+    let t = Table::new(rows, vec![width_constraint; size as usize])
         .bg(app.config.colors.buffer_bg)
         .column_spacing(0);
 
@@ -99,7 +155,7 @@ fn render_game(f: &mut Frame, app: &mut App, area: Rect) {
         .direction(Direction::Vertical)
         .constraints([
             Constraint::Fill(1),
-            Constraint::Min(square_size*4),
+            Constraint::Min(square_size * size),
             Constraint::Fill(1),
         ])
         .split(area);
@@ -107,11 +163,250 @@ fn render_game(f: &mut Frame, app: &mut App, area: Rect) {
         .direction(Direction::Horizontal)
         .constraints([
             Constraint::Fill(1),
-            Constraint::Min(square_size*8),
+            Constraint::Min(square_size * size * 2),
             Constraint::Fill(1),
         ])
         .split(vertical_layout[1])[1];
     f.render_stateful_widget(t, rect, &mut app.tablestate);
+    rect
+}
+
+fn render_main_menu(f: &mut Frame, app: &mut App, rects: Rc<[Rect]>, config_highlight: Style) {
+    let entries = app.menu_entries();
+    let rows = entries.iter().map(|entry| Row::new(vec![Cell::from(Line::from(entry.name()).alignment(Alignment::Center))])).collect_vec();
+    let popup = Table::new(rows, [Constraint::Min(10)])
+        .style(Style::default().fg(Color::LightYellow))
+        .highlight_style(config_highlight)
+        .block(
+            Block::default()
+                .title("Main Menu")
+                .borders(Borders::ALL)
+                .border_type(BorderType::Thick)
+        );
+    let area = centered_rect(rects[1], 30, entries.len() as u16 + 2);
+    app.popup_area = area;
+    f.render_stateful_widget(popup, area, &mut app.tablestate);
+}
+
+// the options for the next game with a start button below them
+fn render_setup(f: &mut Frame, app: &mut App, rects: Rc<[Rect]>, config_highlight: Style, title: &str) {
+    let options = app.options();
+    let mut rows = options.iter().map(|option| option_row(app, option)).collect_vec();
+    rows.push(Row::new(vec![Cell::from("Start")]));
+    let popup = Table::new(
+        rows,
+        [
+            Constraint::Min(10),
+            Constraint::Min(5),
+        ],
+    )
+        .style(Style::default().fg(Color::LightYellow))
+        .highlight_style(config_highlight)
+        .block(
+            Block::default()
+                .title(title)
+                .borders(Borders::ALL)
+                .border_type(BorderType::Thick)
+        );
+    let area = centered_rect(rects[1], 50, options.len() as u16 + 3);
+    app.popup_area = area;
+    f.render_stateful_widget(popup, area, &mut app.tablestate);
+}
+
+fn render_daily(f: &mut Frame, app: &mut App, rects: Rc<[Rect]>, config_highlight: Style) {
+    let today = record::today();
+    let results = app.history.iter().rev().filter(|r| r.variant == Variant::Daily && r.date.is_some()).collect_vec();
+    let played = !record::daily_available(&app.history, &today);
+
+    let mut rows = vec![
+        Row::new(vec![Cell::from(if played { "Practice today's challenge" } else { "Play today's challenge" })]),
+        Row::new(vec![Cell::from(if results.is_empty() { "No daily challenges played yet." } else { "Past results:" })]).fg(Color::Yellow),
+    ];
+    rows.extend(results.iter().map(|result| Row::new(vec![
+        Cell::from(format!("{}  {:<4}  {:>7} points  {:>5}  {:>4} moves",
+                           result.date.as_deref().unwrap_or_default(),
+                           if result.won { "won" } else { "lost" },
+                           result.score,
+                           result.max_tile,
+                           result.moves)),
+    ])));
+
+    let area = centered_rect(rects[1], 60, rows.len() as u16 + 2);
+    let popup = Table::new(rows, [Constraint::Min(10)])
+        .style(Style::default().fg(Color::LightYellow))
+        .highlight_style(config_highlight)
+        .block(
+            Block::default()
+                .title(format!("Daily Challenge {today}"))
+                .borders(Borders::ALL)
+                .border_type(BorderType::Thick)
+        );
+    app.popup_area = area;
+    f.render_stateful_widget(popup, area, &mut app.tablestate);
+}
+
+fn render_puzzles(f: &mut Frame, app: &mut App, rects: Rc<[Rect]>, config_highlight: Style) {
+    let rows = app.puzzles.iter().map(|puzzle| Row::new(vec![
+        Cell::from(if app.solved.contains(&puzzle.id()) { "✓" } else { "" }).fg(Color::LightGreen),
+        Cell::from(puzzle.pack.clone()),
+        Cell::from(puzzle.name.clone()),
+        Cell::from(format!("{0}x{0}", puzzle.board.len())),
+        Cell::from(puzzle.describe()),
+    ])).collect_vec();
+    let solved = app.puzzles.iter().filter(|puzzle| app.solved.contains(&puzzle.id())).count();
+
+    let popup = Table::new(
+        rows,
+        [
+            Constraint::Length(1),
+            Constraint::Length(12),
+            Constraint::Length(16),
+            Constraint::Length(4),
+            Constraint::Min(10),
+        ],
+    )
+        .style(Style::default().fg(Color::LightYellow))
+        .highlight_style(config_highlight)
+        .block(
+            Block::default()
+                .title(format!("Puzzles ({solved}/{} solved)", app.puzzles.len()))
+                .borders(Borders::ALL)
+                .border_type(BorderType::Thick)
+        );
+    let area = centered_rect(rects[1], 80, app.puzzles.len() as u16 + 2);
+    app.popup_area = area;
+    f.render_stateful_widget(popup, area, &mut app.tablestate);
+}
+
+fn render_stats(f: &mut Frame, app: &mut App, rects: Rc<[Rect]>, config_highlight: Style) {
+    let stats = Stats::new(&app.history, app.stats_filter);
+    let block = Block::default()
+        .title("Statistics")
+        .borders(Borders::ALL)
+        .border_type(BorderType::Thick)
+        .style(Style::default().fg(Color::LightYellow));
+    let area = centered_rect(rects[1], 90, rects[1].height);
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+    app.popup_area = area;
+
+    let layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(3), Constraint::Min(0)])
+        .split(inner);
+
+    let filter = |value: Option<String>| option_arrows(value.unwrap_or(String::from("all")), &[]);
+    let filters = Table::new(
+        vec![
+            Row::new(vec![
+                Cell::from("Board Size:"),
+                Cell::from(filter(app.stats_filter.field_size.map(|size| format!("{size}x{size}")))),
+            ]),
+            Row::new(vec![
+                Cell::from("Variant:"),
+                Cell::from(filter(app.stats_filter.category.map(|category| category.name()))),
+            ]),
+        ],
+        [Constraint::Length(12), Constraint::Min(10)],
+    )
+        .highlight_style(config_highlight);
+    f.render_stateful_widget(filters, layout[0], &mut app.tablestate);
+
+    if stats.games == 0 {
+        f.render_widget(Paragraph::new("No finished games yet."), layout[1]);
+        return;
+    }
+
+    let body = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Length(28), Constraint::Min(0)])
+        .split(layout[1]);
+    let charts = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Fill(1), Constraint::Fill(1)])
+        .split(body[1]);
+
+    let summary = [
+        ("Games played:", stats.games.to_string()),
+        ("Wins:", stats.wins.to_string()),
+        ("Best score:", stats.best_score.to_string()),
+        ("Best tile:", stats.best_tile.to_string()),
+        ("Average score:", stats.average_score.to_string()),
+        ("Longest game:", format!("{} moves", stats.longest_game)),
+        ("Total merges:", stats.total_merges.to_string()),
+        ("Best streak:", stats.best_streak.to_string()),
+        ("Current streak:", stats.current_streak.to_string()),
+    ];
+    let summary = Table::new(
+        summary.into_iter().map(|(name, value)| Row::new(vec![Cell::from(name), Cell::from(value).fg(Color::LightCyan)])),
+        [Constraint::Length(16), Constraint::Min(5)],
+    );
+    f.render_widget(summary, body[0]);
+
+    let tiles = stats.max_tiles.iter().map(|(tile, games)| (tile.to_string(), *games as u64)).collect_vec();
+    let histogram = BarChart::default()
+        .block(Block::default().title("Highest tile per game").borders(Borders::TOP))
+        .data(&tiles.iter().map(|(tile, games)| (tile.as_str(), *games)).collect_vec())
+        .bar_width(5)
+        .bar_style(Style::default().fg(Color::LightCyan))
+        .value_style(Style::default().fg(Color::Black).bg(Color::LightCyan));
+    f.render_widget(histogram, charts[0]);
+
+    let win_rates = stats.win_values.iter().map(|(value, (played, won))| Bar::default()
+        .value((*won * 100 / *played) as u64)
+        .text_value(format!("{won}/{played}"))
+        .label(Line::from(value.to_string()))
+    ).collect_vec();
+    let win_chart = BarChart::default()
+        .block(Block::default().title("Win rate per win value").borders(Borders::TOP))
+        .data(BarGroup::default().bars(&win_rates))
+        .max(100)
+        .bar_width(5)
+        .bar_style(Style::default().fg(Color::LightGreen))
+        .value_style(Style::default().fg(Color::Black).bg(Color::LightGreen));
+    f.render_widget(win_chart, charts[1]);
+}
+
+fn render_replays(f: &mut Frame, app: &mut App, rects: Rc<[Rect]>, config_highlight: Style) {
+    let block = Block::default()
+        .title("Replays")
+        .borders(Borders::ALL)
+        .border_type(BorderType::Thick);
+
+    if app.replays.is_empty() {
+        let area = centered_rect(rects[1], 50, 3);
+        let popup = Paragraph::new("No replays yet, they are saved when a game is finished.")
+            .style(Style::default().fg(Color::LightYellow))
+            .block(block);
+        app.popup_area = area;
+        f.render_widget(popup, area);
+        return;
+    }
+
+    let rows = app.replays.iter().map(|(path, replay)| {
+        let finished = record::replay_time(path).map(record::format_timestamp).unwrap_or_default();
+        Row::new(vec![
+            Cell::from(finished),
+            Cell::from(format!("{0}x{0}", replay.field_size)),
+            Cell::from(replay.win_value.to_string()),
+            Cell::from(format!("{} moves", replay.moves.len())),
+        ])
+    }).collect_vec();
+    let popup = Table::new(
+        rows,
+        [
+            Constraint::Length(17),
+            Constraint::Length(4),
+            Constraint::Length(6),
+            Constraint::Min(5),
+        ],
+    )
+        .style(Style::default().fg(Color::LightYellow))
+        .highlight_style(config_highlight)
+        .block(block);
+    let area = centered_rect(rects[1], 60, app.replays.len() as u16 + 2);
+    app.popup_area = area;
+    f.render_stateful_widget(popup, area, &mut app.tablestate);
 }
 
 // this function contains the win, loss and regular reset popup
@@ -121,10 +416,12 @@ fn render_reset(f: &mut Frame, app: &mut App, rects: Rc<[Rect]>, game_state: Gam
             GameState::Active => "Are sure you want to reset your current game progress?",
             GameState::Loss => "You lost!",
             GameState::Win => "You won!",
+            GameState::TimeOut => "Time's up!",
+            GameState::OutOfMoves => "Out of moves!",
         }),
         Line::from(match game_state {
             GameState::Active => "",
-            GameState::Loss | GameState::Win => "Do you want to reset and play again or quit?",
+            GameState::Loss | GameState::Win | GameState::TimeOut | GameState::OutOfMoves => "Do you want to reset and play again or go back to the menu?",
         }),
         Line::default(),
         Span::from(
@@ -133,15 +430,16 @@ fn render_reset(f: &mut Frame, app: &mut App, rects: Rc<[Rect]>, game_state: Gam
             if app.selected_option == SelectedOption::Yes { Modifier::REVERSED } else { Modifier::empty() })
         ).to_centered_line(),
         Span::from(
-            if game_state == GameState::Active { "No" } else { "Quit" }
+            if game_state == GameState::Active { "No" } else { "Main Menu" }
         ).style(Style::default().add_modifier(
             if app.selected_option == SelectedOption::No { Modifier::REVERSED } else { Modifier::empty() })
         ).to_centered_line(),
     ])
         .style(Style::default().fg(
             match game_state {
-                GameState::Active | GameState::Loss => Color::LightRed,
-                GameState::Win => Color::LightGreen
+                GameState::Active | GameState::Loss | GameState::OutOfMoves => Color::LightRed,
+                GameState::Win => Color::LightGreen,
+                GameState::TimeOut => Color::LightYellow,
             }))
         .alignment(Alignment::Center)
         .block(
@@ -151,46 +449,66 @@ fn render_reset(f: &mut Frame, app: &mut App, rects: Rc<[Rect]>, game_state: Gam
                         GameState::Active => "Reset",
                         GameState::Loss => "Game Over",
                         GameState::Win => "Win!",
+                        GameState::TimeOut => "Time Out",
+                        GameState::OutOfMoves => "Out of Moves",
                     })
                 .borders(Borders::ALL)
                 .border_type(BorderType::Thick)
         );
-    let area = centered_rect(rects[1], 60, 7);
+    // finished games get a summary below the options
+    let area = centered_rect(rects[1], 60, if game_state == GameState::Active { 7 } else { 17 });
+    app.popup_area = area;
     //f.render_widget(Clear, area); //this clears out the background
     f.render_widget(popup, area);
+
+    if game_state != GameState::Active {
+        let inner = Rect { x: area.x + 1, y: area.y + 6, width: area.width.saturating_sub(2), height: area.height.saturating_sub(7) };
+        render_summary(f, app, inner);
+    }
 }
 
-fn render_config(f: &mut Frame, app: &mut App, rects: Rc<[Rect]>, config_highlight: Style) {
-    let rows = vec![
-        Row::new(vec![
-            Cell::from("Control Mapping:"),
-            Cell::from("Edit"),
-        ]),
-        Row::new(vec![
-            Cell::from("Color Scheme:"),
-            Cell::from("Edit"),
-        ]),
-        Row::new(vec![
-            Cell::from("Field Size:"),
-            Cell::from(option_arrows(FIELD_SIZES[app.config.field_size].to_string(), FIELD_SIZES.iter().map(|i| i.to_string()).collect())),
-        ]),
-        Row::new(vec![
-            Cell::from("Win Value:"),
-            Cell::from(option_arrows(WIN_VALUES[app.config.win_value].to_string(), WIN_VALUES.iter().map(|i| i.to_string()).collect())),
-        ]),
-        Row::new(vec![
-            Cell::from("Show Reset Popup:"),
-            Cell::from(option_arrows(app.config.reset_popup.to_string(), Box::from([]))),
-        ]),
-        Row::new(vec![
-            Cell::from("Win/Loss Animation:"),
-            Cell::from(option_arrows(app.config.ending_animation.to_string(), Box::from([]))),
+fn render_summary(f: &mut Frame, app: &App, area: Rect) {
+    let moves = &app.record.moves;
+    let value = |text: String| Span::styled(text, Style::default().fg(Color::LightCyan));
+
+    let layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(4), Constraint::Min(0)])
+        .split(area);
+
+    let summary = Paragraph::new(vec![
+        Line::from(vec![
+            Span::from("Score: "), value(get_score().to_string()),
+            Span::from("   Highest tile: "), value(max_tile(&app.items).to_string()),
         ]),
-        Row::new(vec![
-            Cell::from("Show Control Info:"),
-            Cell::from(option_arrows(app.config.control_info.to_string(), Box::from([]))),
+        Line::from(vec![
+            Span::from("Moves: "), value(moves.len().to_string()),
+            Span::from("   Time: "), value(format_duration(app.timer.elapsed())),
+            Span::from("   Largest gain: "), value(largest_gain(&app.score_history).to_string()),
         ]),
-    ];
+        Line::from([(Move::Up, "↑"), (Move::Down, "↓"), (Move::Left, "←"), (Move::Right, "→")]
+            .into_iter()
+            .map(|(dir, arrow)| value(format!(" {arrow} {} ", moves.iter().filter(|m| **m == dir).count())))
+            .collect_vec()),
+    ])
+        .style(Style::default().fg(Color::LightYellow))
+        .alignment(Alignment::Center)
+        .block(Block::default().borders(Borders::TOP).title("Summary"));
+    f.render_widget(summary, layout[0]);
+
+    // long games are squeezed into the available width
+    let width = layout[1].width.max(1) as usize;
+    let scores = app.score_history.chunks(app.score_history.len().div_ceil(width).max(1)).map(|chunk| chunk[chunk.len() - 1]).collect_vec();
+    let sparkline = Sparkline::default()
+        .block(Block::default().title("Score over time"))
+        .data(&scores)
+        .style(Style::default().fg(Color::LightCyan));
+    f.render_widget(sparkline, layout[1]);
+}
+
+fn render_config(f: &mut Frame, app: &mut App, rects: Rc<[Rect]>, config_highlight: Style) {
+    let options = app.options();
+    let rows = options.iter().map(|option| option_row(app, option)).collect_vec();
     let popup = Table::new(
         rows,
         [
@@ -206,21 +524,15 @@ fn render_config(f: &mut Frame, app: &mut App, rects: Rc<[Rect]>, config_highlig
                 .borders(Borders::ALL)
                 .border_type(BorderType::Thick)
         );
-    let area = centered_rect(rects[1], 50, 9);
+    let area = centered_rect(rects[1], 50, options.len() as u16 + 2);
+    app.popup_area = area;
     f.render_stateful_widget(popup, area, &mut app.tablestate);
 }
 
 fn render_keymap(f: &mut Frame, app: &mut App, rects: Rc<[Rect]>, config_highlight: Style) {
-    let rows = vec![
-        keymap_row("Move Up:", &app.config.keymap.up),
-        keymap_row("Move Down:", &app.config.keymap.down),
-        keymap_row("Move Left:", &app.config.keymap.left),
-        keymap_row("Move Right:", &app.config.keymap.right),
-        keymap_row("Exit:", &app.config.keymap.exit),
-        keymap_row("Reset:", &app.config.keymap.reset),
-        keymap_row("Confirm:", &app.config.keymap.confirm),
-        keymap_row("Open Config:", &app.config.keymap.config),
-    ];
+    let rows = KEYMAP_ROWS.iter()
+        .map(|(label, context, action)| keymap_row(label, app.config.keymap.bindings(*context, *action)))
+        .collect_vec();
     let popup = Table::new(
         rows,
         [
@@ -236,18 +548,16 @@ fn render_keymap(f: &mut Frame, app: &mut App, rects: Rc<[Rect]>, config_highlig
                 .borders(Borders::ALL)
                 .border_type(BorderType::Double)
         );
-    let area = centered_rect(rects[1], 50, 10);
+    let area = centered_rect(rects[1], 50, KEYMAP_ROWS.len() as u16 + 2);
+    app.popup_area = area;
     f.render_stateful_widget(popup, area, &mut app.tablestate);
 }
 
 fn render_colors(f: &mut Frame, app: &mut App, rects: Rc<[Rect]>, config_highlight: Style) {
-    let rows = vec![
-        color_row("Classic:", 20, &["#141414", "#C82828", "#FFC828"]),
-        color_row("Rainbow:", 20, &["#141414", "#C82828", "#FFC828"]),
-        color_row("Deuteranopia:", 20, &["#141414", "#C82828", "#FFC828"]),
-        color_row("Protanopia:", 20, &["#141414", "#C82828", "#FFC828"]),
-        color_row("Tritanopia:", 20, &["#141414", "#C82828", "#FFC828"]),
-    ];
+    let rows = Theme::ALL.iter().map(|theme| {
+        let name = if *theme == app.config.theme { format!("{}: *", theme.name()) } else { format!("{}:", theme.name()) };
+        color_row(name, 20, theme.stops())
+    }).collect_vec();
     let popup = Table::new(
         rows,
         [
@@ -264,11 +574,149 @@ fn render_colors(f: &mut Frame, app: &mut App, rects: Rc<[Rect]>, config_highlig
                 .border_type(BorderType::Double)
         );
     let area = centered_rect(rects[1], 50, 7);
+    app.popup_area = area;
     f.render_stateful_widget(popup, area, &mut app.tablestate);
 }
 
+fn render_notice(f: &mut Frame, app: &mut App, rects: Rc<[Rect]>) {
+    let mut lines = app.notices.iter().map(|n| Line::from(n.as_str())).collect_vec();
+    lines.push(Line::default());
+    lines.push(Line::from(format!("({}) continue", app.config.keymap.bindings(Context::Popup, Action::Confirm).iter().map(Chord::format).join("/"))).alignment(Alignment::Center));
+
+    let area = centered_rect(rects[1], 80, lines.len() as u16 + 2);
+    let popup = Paragraph::new(lines)
+        .style(Style::default().fg(Color::LightYellow))
+        .wrap(Wrap { trim: false })
+        .block(
+            Block::default()
+                .title("Notice")
+                .borders(Borders::ALL)
+                .border_type(BorderType::Thick)
+        );
+    app.popup_area = area;
+    f.render_widget(popup, area);
+}
+
+fn render_help(f: &mut Frame, app: &mut App, rects: Rc<[Rect]>) {
+    let lines = help_lines(app);
+    let area = centered_rect(rects[1], 80, lines.len() as u16 + 2);
+
+    // keeps the last line at the bottom instead of scrolling into nothing
+    let max_scroll = (lines.len() as u16).saturating_sub(area.height.saturating_sub(2));
+    app.help_scroll = app.help_scroll.min(max_scroll);
+
+    let popup = Paragraph::new(lines)
+        .style(Style::default().fg(Color::LightYellow))
+        .scroll((app.help_scroll, 0))
+        .block(
+            Block::default()
+                .title(if max_scroll > 0 { format!("Help ({}/{})", app.help_scroll, max_scroll) } else { String::from("Help") })
+                .borders(Borders::ALL)
+                .border_type(BorderType::Thick)
+        );
+    app.popup_area = area;
+    f.render_widget(popup, area);
+}
+
+fn help_lines(app: &App) -> Vec<Line<'static>> {
+    let heading = |text: &'static str| Line::from(Span::styled(text, Style::default().fg(Color::LightCyan).add_modifier(Modifier::BOLD)));
+    let keymap = &app.config.keymap;
+    let format = |chords: &[Chord]| if chords.is_empty() { String::from("-") } else { chords.iter().map(Chord::format).join(", ") };
+
+    let mut lines = vec![heading("Controls")];
+    for (action, description) in [
+        (Action::Up, "move up, previous row"),
+        (Action::Down, "move down, next row"),
+        (Action::Left, "move left, previous option"),
+        (Action::Right, "move right, next option"),
+        (Action::Reset, "start a new game"),
+        (Action::Config, "open/close the config"),
+        (Action::Confirm, "select, edit a setting"),
+        (Action::Back, "go back, leave game/replay"),
+        (Action::Help, "open/close this help"),
+        (Action::Exit, "quit"),
+    ] {
+        // the game bindings are shown, other contexts only if they differ
+        let game = keymap.bindings(Context::Game, action);
+        let mut keys = format(game);
+        for context in Context::ALL.into_iter().skip(1) {
+            let bindings = keymap.bindings(context, action);
+            if bindings != game {
+                keys.push_str(&format!(" ({}: {})", context.name(), format(bindings)));
+            }
+        }
+        lines.push(Line::from(format!("  {description:<28}{keys}")));
+    }
+
+    let size = app.record.field_size;
+    let rule = app.record.variant.merge_rule();
+    lines.extend([
+        Line::default(),
+        heading("Rules"),
+        Line::from(format!("  The board has {size}x{size} fields. Every move slides all tiles as far as possible.")),
+        Line::from(match rule {
+            MergeRule::Doubling => "  Two equal tiles that collide merge into one tile with their sum, once per move.",
+            MergeRule::Fibonacci => "  Two neighbouring Fibonacci numbers that collide merge into the next one (1+1=2, 1+2=3, 2+3=5, ...), once per move.",
+            MergeRule::Triples => "  Three equal tiles in a row that collide merge into one tile with their sum, once per move.",
+        }),
+        Line::from(format!("  After every move that changes the board, {}.", app.record.spawn_rules().describe())),
+    ]);
+    lines.extend(match app.record.variant {
+        Variant::Sandbox => vec![
+            Line::from("  Sandbox: play from the board set up in the editor until no tiles can be moved anymore."),
+            Line::from(if app.record.no_spawns { "  No new tiles appear after a move." } else { "  New tiles appear as usual." }),
+        ],
+        Variant::Puzzle => {
+            let puzzle = app.record.puzzle.as_ref();
+            vec![
+                Line::from(format!("  Puzzle: {}.", puzzle.map(|p| p.describe()).unwrap_or_default())),
+                Line::from(if puzzle.is_some_and(|p| p.spawns.is_some()) { "  The tiles that appear are part of the puzzle, they are the same every time." } else { "  The tiles that appear are random, the starting board is always the same." }),
+            ]
+        }
+        Variant::Daily => vec![
+            Line::from(format!("  Daily challenge: everyone gets the same tiles today, reach a tile of {} to win.", app.record.win_value)),
+            Line::from(if app.record.date.is_some() { "  This is your official attempt, only the first finished game of the day counts." } else { "  Today's attempt is already recorded, this game is practice." }),
+        ],
+        Variant::Blockers => vec![
+            Line::from(format!("  Blockers: {} grey fields can't be passed, tiles stop in front of them and never merge with them.", app.record.blockers)),
+            Line::from(format!("  You win when a tile reaches {}, you lose when no tile can be moved anymore.", app.record.win_value)),
+        ],
+        Variant::Fibonacci | Variant::Triples => vec![
+            Line::from(format!("  {}: you win when a tile reaches {}.", if rule == MergeRule::Fibonacci { "Fibonacci" } else { "Triples" }, app.record.win_value)),
+            Line::from("  You lose when the board is full and no tiles can be merged anymore."),
+        ],
+        Variant::Classic => vec![
+            Line::from(format!("  You win when a tile reaches {}.", app.record.win_value)),
+            Line::from("  You lose when the board is full and no tiles can be merged anymore."),
+        ],
+        Variant::LimitedMoves => vec![
+            Line::from(format!("  Limited moves: reach a tile of {} within {} moves.", app.record.win_value, app.record.move_limit.unwrap_or_default())),
+            Line::from("  The game is lost when the moves run out or the board is full, moves that change nothing don't count."),
+        ],
+        Variant::Blitz => vec![
+            Line::from(format!("  Blitz: score as many points as possible in {}.", format_duration(app.record.time_limit.unwrap_or_default()))),
+            Line::from("  The game ends when the time is up or the board is full, the clock stops while popups are open."),
+        ],
+    });
+    lines.extend([
+        Line::default(),
+        heading("Scoring"),
+        Line::from("  Every merge adds the value of the new tile to the score."),
+        Line::from("  The highscore is the best score of all finished games, it is updated while you play."),
+        Line::default(),
+        heading("Indicators"),
+        Line::from("  2048 (Replay)   a finished game is replayed, only the back, exit and help keys work"),
+        Line::from("  < value >       the setting can be changed with left/right after confirming it"),
+        Line::from("  blinking row    the selected setting is being changed"),
+        Line::from("  theme: *        the color theme that is currently used"),
+        Line::from("  tile colors     get warmer the higher the value, depending on the color theme"),
+    ]);
+    lines
+}
+
 fn render_sidebar(f: &mut Frame, app: &mut App, area: Rect) {
-    let info_footer = Paragraph::new(Line::from(INFO_TEXT))
+    let lines = control_info(&control_entries(app), area.width.saturating_sub(2) as usize, area.height.saturating_sub(2) as usize);
+    let info_footer = Paragraph::new(lines.into_iter().map(Line::from).collect_vec())
         .style(Style::new().fg(app.config.colors.row_fg).bg(app.config.colors.buffer_bg))
         .alignment(Alignment::Center)
         .block(
@@ -280,6 +728,60 @@ fn render_sidebar(f: &mut Frame, app: &mut App, area: Rect) {
     f.render_widget(info_footer, area);
 }
 
+// what the keys do in the current context, for the control info footer
+fn control_entries(app: &App) -> Vec<(Vec<String>, &'static str)> {
+    let context = app.context();
+    let keys = |actions: &[Action]| actions.iter()
+        .flat_map(|action| app.config.keymap.bindings(context, *action))
+        .map(Chord::format)
+        .collect_vec();
+
+    if app.screen() == Screen::Editor {
+        return vec![
+            (keys(&[Action::Up, Action::Down, Action::Left, Action::Right]), if app.option_lock { "change value" } else { "select field" }),
+            (keys(&[Action::Confirm]), if app.option_lock { "done" } else { "edit field" }),
+            (keys(&[Action::Reset]), "clear board"),
+            (keys(&[Action::Back]), "back"),
+            (keys(&[Action::Help]), "help"),
+            (keys(&[Action::Exit]), "quit"),
+        ];
+    }
+
+    match context {
+        Context::Game => vec![
+            (keys(&[Action::Up]), "move up"),
+            (keys(&[Action::Down]), "move down"),
+            (keys(&[Action::Left]), "move left"),
+            (keys(&[Action::Right]), "move right"),
+            (keys(&[Action::Reset]), "reset"),
+            (keys(&[Action::Config]), "config"),
+            (keys(&[Action::Help]), "help"),
+            (keys(&[Action::Exit]), "quit"),
+        ],
+        Context::Menu => vec![
+            (keys(&[Action::Up, Action::Down]), "select"),
+            (keys(&[Action::Left, Action::Right]), "change"),
+            (keys(&[Action::Confirm]), "confirm"),
+            (keys(&[Action::Back]), "back"),
+            (keys(&[Action::Config]), "close"),
+            (keys(&[Action::Help]), "help"),
+            (keys(&[Action::Exit]), "quit"),
+        ],
+        Context::Popup => vec![
+            (keys(&[Action::Left, Action::Right]), "choose"),
+            (keys(&[Action::Confirm]), "confirm"),
+            (keys(&[Action::Back]), "back"),
+            (keys(&[Action::Help]), "help"),
+            (keys(&[Action::Exit]), "quit"),
+        ],
+        Context::Replay => vec![
+            (keys(&[Action::Back]), "leave"),
+            (keys(&[Action::Help]), "help"),
+            (keys(&[Action::Exit]), "quit"),
+        ],
+    }
+}
+
 fn centered_rect(r: Rect, percent_x: u16, height: u16) -> Rect {
     let popup_layout = Layout::default()
         .direction(Direction::Vertical)
@@ -300,9 +802,9 @@ fn centered_rect(r: Rect, percent_x: u16, height: u16) -> Rect {
         .split(popup_layout[1])[1]
 }
 
-fn option_arrows<T: PartialEq + Display>(value: T, options: Box<[T]>) -> String {
+fn option_arrows<T: PartialEq + Display>(value: T, options: &[T]) -> String {
     // in order to always display option arrows, options has to be empty (avoids unnecessary overhead)
-    if options.len() == 0 {
+    if options.is_empty() {
         return format!("< {} >", value);
     }
 
@@ -314,16 +816,36 @@ fn option_arrows<T: PartialEq + Display>(value: T, options: Box<[T]>) -> String
     ).trim().to_string()
 }
 
+fn option_row<'a>(app: &App, option: &MenuOption) -> Row<'a> {
+    let value = match &option.setting {
+        Setting::Toggle { get, .. } => option_arrows(get(app).to_string(), &[]),
+        Setting::Choice { values, get, .. } => {
+            let values = values();
+            option_arrows(values[get(app)].clone(), &values)
+        }
+        Setting::Range { min, max, get, .. } => {
+            let value = get(app);
+            format!("{} {value} {}", if value > *min { "<" } else { "" }, if value < *max { ">" } else { "" }).trim().to_string()
+        }
+        Setting::Submenu(_) => String::from("Edit"),
+    };
+
+    Row::new(vec![
+        Cell::from(format!("{}:", option.name)),
+        Cell::from(value),
+    ])
+}
+
 #[inline]
-fn keymap_row<'a>(text: &'a str, keys: &[KeyCode]) -> Row<'a> {
+fn keymap_row<'a>(text: &'a str, keys: &[Chord]) -> Row<'a> {
     Row::new(vec![
         Cell::from(text),
-        Cell::from(keys.iter().map(|k| format_keycode(k)).collect_vec().join(", ")),
+        Cell::from(keys.iter().map(Chord::format).collect_vec().join(", ")),
     ])
 }
 
 #[inline]
-fn color_row<'a>(text: &'a str, width: u16, colors: &'a [&str]) -> Row<'a> {
+fn color_row<'a>(text: String, width: u16, colors: &'a [&str]) -> Row<'a> {
     Row::new(vec![
         Cell::from(text),
         Cell::from(generate_color_bar(width, colors)),
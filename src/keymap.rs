@@ -0,0 +1,444 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use itertools::Itertools;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::util::{ConfigTable, format_keycode};
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Up,
+    Down,
+    Left,
+    Right,
+    Exit,
+    Reset,
+    Confirm,
+    Back,
+    Config,
+    Help,
+}
+
+impl Action {
+    pub const ALL: [Action; 10] = [Action::Up, Action::Down, Action::Left, Action::Right, Action::Exit, Action::Reset, Action::Confirm, Action::Back, Action::Config, Action::Help];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Up => "up",
+            Action::Down => "down",
+            Action::Left => "left",
+            Action::Right => "right",
+            Action::Exit => "exit",
+            Action::Reset => "reset",
+            Action::Confirm => "confirm",
+            Action::Back => "back",
+            Action::Config => "config",
+            Action::Help => "help",
+        }
+    }
+}
+
+// the same key can mean something different depending on what is shown
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Context {
+    Game,
+    Menu,
+    Popup,
+    Replay,
+}
+
+impl Context {
+    pub const ALL: [Context; 4] = [Context::Game, Context::Menu, Context::Popup, Context::Replay];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Context::Game => "game",
+            Context::Menu => "menu",
+            Context::Popup => "popup",
+            Context::Replay => "replay",
+        }
+    }
+}
+
+// the bindings listed on the keymap screen, each in the context where it is used
+pub const KEYMAP_ROWS: [(&str, Context, Action); 9] = [
+    ("Move Up:", Context::Game, Action::Up),
+    ("Move Down:", Context::Game, Action::Down),
+    ("Move Left:", Context::Game, Action::Left),
+    ("Move Right:", Context::Game, Action::Right),
+    ("Exit:", Context::Game, Action::Exit),
+    ("Reset:", Context::Game, Action::Reset),
+    ("Confirm:", Context::Popup, Action::Confirm),
+    ("Open Config:", Context::Game, Action::Config),
+    ("Help:", Context::Game, Action::Help),
+];
+
+// a single key press with its modifiers, written like "Ctrl+z", "Shift+r" or "PageUp" in the config
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct KeyBinding {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl KeyBinding {
+    // terminals disagree on whether shifted characters come with the shift modifier, so it is folded into the character
+    pub fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        let mut modifiers = modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT);
+        let code = match code {
+            KeyCode::Char(c) if modifiers.contains(KeyModifiers::SHIFT) => {
+                modifiers.remove(KeyModifiers::SHIFT);
+                KeyCode::Char(c.to_ascii_uppercase())
+            }
+            KeyCode::BackTab => {
+                modifiers.remove(KeyModifiers::SHIFT);
+                KeyCode::BackTab
+            }
+            code => code,
+        };
+
+        Self { code, modifiers }
+    }
+
+    pub fn key(code: KeyCode) -> Self {
+        Self::new(code, KeyModifiers::NONE)
+    }
+
+    // for the interface, arrows and upper case letters
+    pub fn format(&self) -> String {
+        format!("{}{}", modifier_prefix(self.modifiers), format_keycode(&self.code))
+    }
+}
+
+impl From<KeyEvent> for KeyBinding {
+    fn from(event: KeyEvent) -> Self {
+        Self::new(event.code, event.modifiers)
+    }
+}
+
+impl FromStr for KeyBinding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.chars().count() == 1 {
+            return Ok(Self::key(KeyCode::Char(s.chars().next().unwrap())));
+        }
+
+        let (modifiers, key) = match s.strip_suffix("++") {
+            Some(modifiers) => (modifiers, "+"),
+            None => s.rsplit_once('+').unwrap_or(("", s)),
+        };
+
+        let mut parsed = KeyModifiers::NONE;
+        for modifier in modifiers.split('+').filter(|m| !m.is_empty()) {
+            parsed |= match modifier.to_lowercase().as_str() {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "alt" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => return Err(format!("unknown modifier `{modifier}`")),
+            };
+        }
+
+        let code = match key.to_lowercase().as_str() {
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "enter" => KeyCode::Enter,
+            "esc" | "escape" => KeyCode::Esc,
+            "backspace" => KeyCode::Backspace,
+            "tab" => KeyCode::Tab,
+            "backtab" => KeyCode::BackTab,
+            "space" => KeyCode::Char(' '),
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            "delete" | "del" => KeyCode::Delete,
+            "insert" => KeyCode::Insert,
+            name => match (name.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()), key.chars().exactly_one()) {
+                (Some(n), _) if (1..=12).contains(&n) => KeyCode::F(n),
+                (_, Ok(c)) => KeyCode::Char(c),
+                _ => return Err(format!("unknown key `{key}`")),
+            },
+        };
+
+        Ok(Self::new(code, parsed))
+    }
+}
+
+impl Display for KeyBinding {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let key = match self.code {
+            KeyCode::Char(' ') => String::from("Space"),
+            KeyCode::Char(c) => c.to_string(),
+            KeyCode::F(n) => format!("F{n}"),
+            code => format!("{code:?}"),
+        };
+        write!(f, "{}{key}", modifier_prefix(self.modifiers))
+    }
+}
+
+fn modifier_prefix(modifiers: KeyModifiers) -> String {
+    let mut prefix = String::new();
+    if modifiers.contains(KeyModifiers::CONTROL) {
+        prefix.push_str("Ctrl+");
+    }
+    if modifiers.contains(KeyModifiers::ALT) {
+        prefix.push_str("Alt+");
+    }
+    if modifiers.contains(KeyModifiers::SHIFT) {
+        prefix.push_str("Shift+");
+    }
+    prefix
+}
+
+// one or more key presses in a row, separated by spaces in the config (e.g. "g g")
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Chord(pub Vec<KeyBinding>);
+
+impl Chord {
+    pub fn format(&self) -> String {
+        self.0.iter().map(KeyBinding::format).join(" ")
+    }
+}
+
+impl FromStr for Chord {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let keys = s.split_whitespace().map(KeyBinding::from_str).collect::<Result<Vec<_>, _>>()?;
+        if keys.is_empty() {
+            return Err(String::from("empty key binding"));
+        }
+        Ok(Self(keys))
+    }
+}
+
+impl Display for Chord {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0.iter().join(" "))
+    }
+}
+
+impl Serialize for Chord {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Chord {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+pub type Bindings = BTreeMap<Action, Vec<Chord>>;
+
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Preset {
+    #[default]
+    Default,
+    Vim,
+}
+
+impl Preset {
+    pub const ALL: [Preset; 2] = [Preset::Default, Preset::Vim];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Preset::Default => "default",
+            Preset::Vim => "vim",
+        }
+    }
+
+    fn bindings(&self) -> Bindings {
+        let (up, down, left, right) = match self {
+            Preset::Default => (["w", "Up"], ["s", "Down"], ["a", "Left"], ["d", "Right"]),
+            Preset::Vim => (["k", "Up"], ["j", "Down"], ["h", "Left"], ["l", "Right"]),
+        };
+        let exit: &[&str] = match self {
+            Preset::Default => &["q", "Esc", "Ctrl+c"],
+            Preset::Vim => &["q", "Esc", "Ctrl+c", "Z Z"],
+        };
+
+        [
+            (Action::Up, up.as_slice()),
+            (Action::Down, down.as_slice()),
+            (Action::Left, left.as_slice()),
+            (Action::Right, right.as_slice()),
+            (Action::Exit, exit),
+            (Action::Reset, &["r"]),
+            (Action::Confirm, &["Enter"]),
+            (Action::Back, &["Backspace"]),
+            (Action::Config, &["c"]),
+            (Action::Help, &["?", "F1"]),
+        ].into_iter()
+            .map(|(action, chords)| (action, chords.iter().map(|c| c.parse().unwrap()).collect()))
+            .collect()
+    }
+}
+
+pub enum Lookup {
+    Action(Action),
+    // the keys are the start of a chord
+    Pending,
+    None,
+}
+
+// the preset provides the bindings for every context, the config file only contains what is changed:
+// bindings of a context replace the global ones of the same action, which replace the ones of the preset
+#[derive(Clone, Serialize, Deserialize)]
+pub struct KeyMap {
+    pub preset: Preset,
+    pub global: Bindings,
+    pub game: Bindings,
+    pub menu: Bindings,
+    pub popup: Bindings,
+    pub replay: Bindings,
+    #[serde(skip)]
+    preset_bindings: Bindings,
+}
+
+impl KeyMap {
+    pub fn default() -> KeyMap {
+        KeyMap::preset(Preset::default())
+    }
+
+    pub fn preset(preset: Preset) -> KeyMap {
+        KeyMap {
+            preset,
+            global: Bindings::new(),
+            game: Bindings::new(),
+            menu: Bindings::new(),
+            popup: Bindings::new(),
+            replay: Bindings::new(),
+            preset_bindings: preset.bindings(),
+        }
+    }
+
+    // the bindings of the config file stay, only what they are based on changes
+    pub fn set_preset(&mut self, preset: Preset) {
+        self.preset = preset;
+        self.preset_bindings = preset.bindings();
+    }
+
+    pub fn from_map(mut map: ConfigTable) -> KeyMap {
+        let mut keymap = KeyMap::preset(map.get("preset", Preset::default()));
+
+        keymap.global = read_bindings(map.table("global"));
+        keymap.game = read_bindings(map.table(Context::Game.name()));
+        keymap.menu = read_bindings(map.table(Context::Menu.name()));
+        keymap.popup = read_bindings(map.table(Context::Popup.name()));
+        keymap.replay = read_bindings(map.table(Context::Replay.name()));
+        keymap
+    }
+
+    fn context(&self, context: Context) -> &Bindings {
+        match context {
+            Context::Game => &self.game,
+            Context::Menu => &self.menu,
+            Context::Popup => &self.popup,
+            Context::Replay => &self.replay,
+        }
+    }
+
+    pub fn bindings(&self, context: Context, action: Action) -> &[Chord] {
+        self.context(context).get(&action)
+            .or(self.global.get(&action))
+            .or(self.preset_bindings.get(&action))
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    // an exact match wins over longer chords starting with the same keys
+    pub fn lookup(&self, context: Context, keys: &[KeyBinding]) -> Lookup {
+        let mut pending = false;
+        for action in Action::ALL {
+            for chord in self.bindings(context, action) {
+                if chord.0 == keys {
+                    return Lookup::Action(action);
+                }
+                pending |= chord.0.starts_with(keys);
+            }
+        }
+
+        if pending { Lookup::Pending } else { Lookup::None }
+    }
+}
+
+fn read_bindings(mut map: ConfigTable) -> Bindings {
+    Action::ALL.iter()
+        .filter_map(|action| map.get_chords(action.name()).map(|chords| (*action, chords)))
+        .collect()
+}
+
+#[cfg(test)]
+mod keymap_test {
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+    use super::{Action, Chord, Context, KeyBinding, KeyMap, Lookup, Preset};
+
+    fn key(s: &str) -> KeyBinding {
+        s.parse().unwrap()
+    }
+
+    fn action(keymap: &KeyMap, context: Context, keys: &[&str]) -> Option<Action> {
+        match keymap.lookup(context, &keys.iter().map(|k| key(k)).collect::<Vec<_>>()) {
+            Lookup::Action(action) => Some(action),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_parse_key() {
+        assert_eq!(KeyBinding::key(KeyCode::Char('z')), key("z"));
+        assert_eq!(KeyBinding::new(KeyCode::Char('z'), KeyModifiers::CONTROL), key("Ctrl+z"));
+        assert_eq!(KeyBinding::new(KeyCode::Char('+'), KeyModifiers::ALT), key("alt++"));
+        assert_eq!(KeyBinding::key(KeyCode::PageUp), key("PageUp"));
+        assert_eq!(KeyBinding::key(KeyCode::F(5)), key("F5"));
+        assert_eq!(KeyBinding::key(KeyCode::Char(' ')), key("Space"));
+        assert!("Hyper+x".parse::<KeyBinding>().is_err());
+        assert!("Foo".parse::<KeyBinding>().is_err());
+    }
+
+    #[test]
+    fn test_shift_is_folded_into_characters() {
+        assert_eq!(key("R"), key("Shift+r"));
+        assert_eq!(key("R"), KeyBinding::from(KeyEvent::new(KeyCode::Char('R'), KeyModifiers::SHIFT)));
+        assert_eq!(key("Shift+Up"), KeyBinding::from(KeyEvent::new(KeyCode::Up, KeyModifiers::SHIFT)));
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        for s in ["Ctrl+z", "R", "Up", "Space", "Alt+Enter", "F12", "g g"] {
+            assert_eq!(s, s.parse::<Chord>().unwrap().to_string());
+        }
+    }
+
+    #[test]
+    fn test_context_overrides() {
+        let mut keymap = KeyMap::default();
+        keymap.global.insert(Action::Up, vec!["i".parse().unwrap()]);
+        keymap.menu.insert(Action::Up, vec!["Ctrl+p".parse().unwrap()]);
+
+        assert_eq!(Some(Action::Up), action(&keymap, Context::Game, &["i"]));
+        assert_eq!(None, action(&keymap, Context::Game, &["w"]));
+        assert_eq!(Some(Action::Up), action(&keymap, Context::Menu, &["Ctrl+p"]));
+        assert_eq!(None, action(&keymap, Context::Menu, &["i"]));
+        assert_eq!(Some(Action::Down), action(&keymap, Context::Menu, &["s"]));
+    }
+
+    #[test]
+    fn test_chords() {
+        let keymap = KeyMap::preset(Preset::Vim);
+        assert!(matches!(keymap.lookup(Context::Game, &[key("Z")]), Lookup::Pending));
+        assert_eq!(Some(Action::Exit), action(&keymap, Context::Game, &["Z", "Z"]));
+        assert_eq!(None, action(&keymap, Context::Game, &["Z", "k"]));
+        assert_eq!(Some(Action::Up), action(&keymap, Context::Game, &["k"]));
+    }
+}
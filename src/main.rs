@@ -1,20 +1,21 @@
 use std::{error::Error, fs, io};
 use std::collections::HashMap;
-use std::fs::File;
-use std::io::Write;
-use config::Value;
+use clap::Parser;
 
 use crossterm::{
-    event::{DisableMouseCapture, EnableMouseCapture, KeyCode},
+    event::{DisableFocusChange, DisableMouseCapture, EnableFocusChange, EnableMouseCapture},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
-use crossterm::event::KeyCode::*;
 use ratatui::prelude::*;
 use serde::{Deserialize, Serialize};
 
 use crate::app::{App, run_app};
-use crate::colors::TableColors;
+use crate::cli::{Cli, Command};
+use crate::colors::{TableColors, Theme};
+use crate::keymap::KeyMap;
+use crate::record::GameRecord;
+use crate::settings::{CONFIG_VERSION, ConfigWatcher};
 use crate::util::*;
 
 mod interface;
@@ -22,50 +23,106 @@ mod colors;
 mod movement;
 mod util;
 mod app;
+mod cli;
+mod keymap;
+mod menu;
+mod paths;
+mod puzzle;
+mod record;
+mod settings;
 
 const FIELD_SIZES: [u16; 7] = [3, 4, 5, 6, 7, 8, 9];
 const WIN_VALUES: [u32; 12] = [8, 16, 32, 64, 128, 256, 512, 1024, 2048, 4096, 8192, 16384];
 
 fn main() -> Result<(), Box<dyn Error>> {
-    // setup terminal
+    let cli = Cli::parse();
+    let config_path = match &cli.config {
+        Some(path) => path.clone(),
+        None => {
+            let path = paths::config_file();
+            if paths::migrate_legacy_config(&path)? {
+                println!("Moved config.json from the working directory to {}", path.display());
+            }
+            path
+        }
+    };
+
+    match cli.command {
+        Some(Command::Stats) => {
+            print!("{}", record::stats_report(&record::read_history()));
+            return Ok(());
+        }
+        Some(Command::ResetConfig) => {
+            settings::write_default(&config_path)?;
+            println!("Reset config file {}", config_path.display());
+            return Ok(());
+        }
+        Some(Command::PrintDefaultConfig) => {
+            println!("{}", serde_json::to_string_pretty(&Config::default())?);
+            return Ok(());
+        }
+        None => {}
+    }
+
+    // read these before touching the terminal, so errors are printed normally
+    let load = cli.load.as_deref().map(GameRecord::read).transpose()?;
+    let replay = cli.replay.as_deref().map(GameRecord::read).transpose()?;
+
+    // create/load config file
+    if fs::metadata(&config_path).is_err() {
+        settings::write_default(&config_path)?;
+    }
+    let mut watcher = ConfigWatcher::new(config_path, cli.overrides);
+    let (config, notices) = watcher.load();
+
+    // setup terminal, mouse capture is only needed if any mouse input is enabled
     enable_raw_mode()?;
     let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
+    execute!(stdout, EnterAlternateScreen, EnableFocusChange)?;
+    if config.mouse.any_enabled() {
+        execute!(stdout, EnableMouseCapture)?;
+    }
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    // create/load config file
-    if fs::metadata("config.json").is_err() {
-        let mut file = File::create("config.json")?;
-        file.write_all(&serde_json::to_vec(&Config::default()).unwrap()).expect("Could not write default config file!");
+    // create app and run it, it starts in the main menu unless a game was asked for
+    let skip_menu = load.is_some() || replay.is_some() || cli.seed.is_some();
+    let mut app = match (load, replay) {
+        (Some(save), _) => App::load(config, save),
+        (_, Some(replay)) => App::replay(config, replay),
+        _ => App::new(config, cli.seed),
+    };
+    if skip_menu {
+        app.push(Screen::Game);
     }
-    let config = config::Config::builder()
-        .add_source(config::File::with_name("config.json"))
-        .build()
-        .unwrap();
-
-
-    // create app and run it
-    let app = App::new(Config::read_file(config));
-    let res = run_app(&mut terminal, app);
+    app.watcher = Some(watcher);
+    app.show_notices(notices);
+    let res = run_app(&mut terminal, &mut app);
 
     // restore terminal
     disable_raw_mode()?;
-    execute!(
-        terminal.backend_mut(),
-        LeaveAlternateScreen,
-        DisableMouseCapture
-    )?;
+    // the config might have been reloaded in between, disabling it anyway does no harm
+    execute!(terminal.backend_mut(), LeaveAlternateScreen, DisableMouseCapture, DisableFocusChange)?;
     terminal.show_cursor()?;
 
     if let Err(err) = res {
         println!("{err:?}");
     }
 
+    if let Some(summary) = &app.daily_summary {
+        println!("{summary}");
+    }
+
+    // keep unfinished games around so they can be continued with --load
+    if app.playback.is_none() && app.gamestate == GameState::Active && !app.record.moves.is_empty() {
+        app.saved_record().write(&record::save_path())?;
+        println!("Saved the current game to {}", record::save_path().display());
+    }
+
     Ok(())
 }
 
-#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
 struct Data {
     numbers: Vec<u32>,
 }
@@ -81,16 +138,29 @@ enum GameState {
     Active,
     Loss,
     Win,
+    // the time limit of a blitz game ran out
+    TimeOut,
+    // all moves of a limited moves game were used without reaching the win value
+    OutOfMoves,
 }
 
-#[derive(Eq, PartialEq, Default)]
-enum PopUp {
-    #[default]
-    None,
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+enum Screen {
+    MainMenu,
+    NewGame,
+    Daily,
+    Puzzles,
+    Sandbox,
+    Editor,
+    Game,
     Reset,
     Config,
     Keymap,
     Colors,
+    Notice,
+    Help,
+    Stats,
+    Replays,
 }
 
 #[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
@@ -102,88 +172,196 @@ enum SelectedOption {
 
 #[derive(Serialize, Deserialize)]
 struct Config {
+    version: u64,
     keymap: KeyMap,
+    mouse: MouseConfig,
     colors: TableColors,
+    theme: Theme,
     field_size: usize,
     win_value: usize,
     reset_popup: bool,
     ending_animation: bool, // TODO: implement this :)
     control_info: bool,
+    spawns: SpawnRules,
 }
 
 impl Config {
     fn default() -> Self {
         Self {
+            version: CONFIG_VERSION,
             colors: TableColors::default(),
+            theme: Theme::default(),
             keymap: KeyMap::default(),
+            mouse: MouseConfig::default(),
             field_size: 1,
             win_value: 8,
             reset_popup: true,
             ending_animation: true,
             control_info: true,
+            spawns: SpawnRules::default(),
         }
     }
 
-    fn read_file(config_file: config::Config) -> Self {
+    // never fails, every missing or invalid value is replaced by its default and invalid ones are added to errors
+    fn read_file(config_file: config::Config, errors: &mut Vec<String>) -> Self {
+        let map = config_file.try_deserialize::<HashMap<String, config::Value>>().unwrap_or_else(|e| {
+            errors.push(e.to_string());
+            HashMap::new()
+        });
+        let mut table = ConfigTable::new(map, errors);
+        let default = Config::default();
+
         Self {
-            colors: TableColors::from_map(config_file.get_table("colors").unwrap()),
-            keymap: KeyMap::from_map(config_file.get_table("keymap").unwrap()),
-            field_size: config_file.get_int("field_size").unwrap() as usize,
-            win_value: config_file.get_int("win_value").unwrap() as usize,
-            reset_popup: config_file.get_bool("reset_popup").unwrap(),
-            ending_animation: config_file.get_bool("ending_animation").unwrap(),
-            control_info: config_file.get_bool("control_info").unwrap(),
+            version: table.get("version", default.version),
+            colors: TableColors::from_map(table.table("colors")),
+            theme: table.get("theme", default.theme),
+            keymap: KeyMap::from_map(table.table("keymap")),
+            mouse: MouseConfig::from_map(table.table("mouse")),
+            field_size: table.get_index("field_size", default.field_size, FIELD_SIZES.len()),
+            win_value: table.get_index("win_value", default.win_value, WIN_VALUES.len()),
+            reset_popup: table.get("reset_popup", default.reset_popup),
+            ending_animation: table.get("ending_animation", default.ending_animation),
+            control_info: table.get("control_info", default.control_info),
+            spawns: SpawnRules::from_map(table.table("spawns")),
         }
     }
 }
 
 #[derive(Clone, Serialize, Deserialize)]
-pub struct KeyMap {
-    up: Vec<KeyCode>,
-    down: Vec<KeyCode>,
-    left: Vec<KeyCode>,
-    right: Vec<KeyCode>,
-    exit: Vec<KeyCode>,
-    reset: Vec<KeyCode>,
-    confirm: Vec<KeyCode>,
-    back: Vec<KeyCode>,
-    config: Vec<KeyCode>,
+pub struct MouseConfig {
+    drag: bool,
+    click: bool,
+    scroll: bool,
+    drag_distance: u16,
 }
 
-impl KeyMap {
-    fn default() -> KeyMap {
-        KeyMap {
-            up: vec![Char('w'), Up],
-            down: vec![Char('s'), Down],
-            left: vec![Char('a'), Left],
-            right: vec![Char('d'), Right],
-            exit: vec![Char('q'), Esc],
-            reset: vec![Char('r')],
-            confirm: vec![Enter],
-            back: vec![Backspace],
-            config: vec![Char('c')],
+impl MouseConfig {
+    fn default() -> MouseConfig {
+        MouseConfig {
+            drag: true,
+            click: true,
+            scroll: true,
+            drag_distance: 3,
         }
     }
 
-    fn from_map(map: HashMap<String, Value>) -> KeyMap {
-        KeyMap {
-            up: deserialize_keycode_vec(&map, "up"),
-            down: deserialize_keycode_vec(&map, "down"),
-            left: deserialize_keycode_vec(&map, "left"),
-            right: deserialize_keycode_vec(&map, "right"),
-            exit: deserialize_keycode_vec(&map, "exit"),
-            reset: deserialize_keycode_vec(&map, "reset"),
-            confirm: deserialize_keycode_vec(&map, "confirm"),
-            back: deserialize_keycode_vec(&map, "back"),
-            config: deserialize_keycode_vec(&map, "config"),
+    fn from_map(mut map: ConfigTable) -> MouseConfig {
+        let default = MouseConfig::default();
+        let mut drag_distance = map.get("drag_distance", default.drag_distance);
+        if drag_distance == 0 {
+            map.error("drag_distance", String::from("has to be at least 1"));
+            drag_distance = default.drag_distance;
+        }
+
+        MouseConfig {
+            drag: map.get("drag", default.drag),
+            click: map.get("click", default.click),
+            scroll: map.get("scroll", default.scroll),
+            drag_distance,
         }
     }
+
+    fn any_enabled(&self) -> bool {
+        self.drag || self.click || self.scroll
+    }
 }
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum Direction {
     Up,
     Down,
     Left,
     Right,
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod config_test {
+    use std::collections::BTreeMap;
+
+    use ratatui::style::Color;
+
+    use crate::keymap::{Action, Chord, Context, Preset};
+    use crate::util::{SpawnPosition, SpawnRules};
+    use super::Config;
+
+    fn chords(keys: &[&str]) -> Vec<Chord> {
+        keys.iter().map(|k| k.parse().unwrap()).collect()
+    }
+
+    fn read(json: &str) -> (Config, Vec<String>) {
+        let file = config::Config::builder()
+            .add_source(config::File::from_str(json, config::FileFormat::Json))
+            .build()
+            .unwrap();
+        let mut errors = Vec::new();
+        (Config::read_file(file, &mut errors), errors)
+    }
+
+    #[test]
+    fn test_read_empty_config() {
+        let (config, errors) = read("{}");
+        assert!(errors.is_empty());
+        assert_eq!(Config::default().field_size, config.field_size);
+        assert_eq!(Config::default().win_value, config.win_value);
+        assert_eq!(Preset::Default, config.keymap.preset);
+        assert!(config.keymap.global.is_empty());
+    }
+
+    #[test]
+    fn test_read_default_config() {
+        let (_, errors) = read(&serde_json::to_string(&Config::default()).unwrap());
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn test_read_partial_config() {
+        let (config, errors) = read(r#"{ "field_size": 2, "keymap": { "preset": "vim", "game": { "up": ["PageUp"] } }, "colors": { "row_fg": "Red" } }"#);
+        assert!(errors.is_empty());
+        assert_eq!(2, config.field_size);
+        assert_eq!(chords(&["PageUp"]), config.keymap.bindings(Context::Game, Action::Up));
+        assert_eq!(chords(&["k", "Up"]), config.keymap.bindings(Context::Menu, Action::Up));
+        assert_eq!(chords(&["j", "Down"]), config.keymap.bindings(Context::Game, Action::Down));
+        assert_eq!(Color::Red, config.colors.row_fg);
+    }
+
+    #[test]
+    fn test_read_out_of_range() {
+        let (config, errors) = read(r#"{ "field_size": 7, "win_value": 12 }"#);
+        assert_eq!(Config::default().field_size, config.field_size);
+        assert_eq!(Config::default().win_value, config.win_value);
+        assert_eq!(2, errors.len());
+        assert!(errors[0].starts_with("field_size:"));
+        assert!(errors[1].starts_with("win_value:"));
+    }
+
+    #[test]
+    fn test_read_invalid_values() {
+        let (config, errors) = read(r#"{ "reset_popup": "maybe", "keymap": { "preset": "emacs", "global": { "up": ["Ctrl+k", "Nope+k"], "down": "s" } }, "mouse": { "drag_distance": 0 } }"#);
+        assert_eq!(Config::default().reset_popup, config.reset_popup);
+        assert_eq!(Preset::Default, config.keymap.preset);
+        assert_eq!(chords(&["Ctrl+k"]), config.keymap.bindings(Context::Game, Action::Up));
+        assert_eq!(chords(&["s", "Down"]), config.keymap.bindings(Context::Game, Action::Down));
+        assert_eq!(Config::default().mouse.drag_distance, config.mouse.drag_distance);
+        assert_eq!(5, errors.len());
+        assert!(errors.iter().any(|e| e.starts_with("keymap.preset:")));
+        assert!(errors.iter().any(|e| e.starts_with("keymap.global.up[1]:")));
+        assert!(errors.iter().any(|e| e.starts_with("keymap.global.down:")));
+        assert!(errors.iter().any(|e| e.starts_with("mouse.drag_distance:")));
+    }
+
+    #[test]
+    fn test_read_spawn_rules() {
+        let (config, errors) = read(r#"{ "spawns": { "weights": { "2": 90, "4": 10, "8": 0 }, "per_move": 2, "position": "opposite_edge" } }"#);
+        assert!(errors.is_empty());
+        assert_eq!(BTreeMap::from([(2, 90), (4, 10), (8, 0)]), config.spawns.weights);
+        assert_eq!(2, config.spawns.per_move);
+        assert_eq!(SpawnRules::default().initial, config.spawns.initial);
+        assert_eq!(SpawnPosition::OppositeEdge, config.spawns.position);
+
+        let (config, errors) = read(r#"{ "spawns": { "weights": { "3": 1, "4": 0 }, "initial": 0 } }"#);
+        assert_eq!(SpawnRules::default(), config.spawns);
+        assert_eq!(3, errors.len());
+        assert!(errors.iter().any(|e| e.starts_with("spawns.weights.3:")));
+        assert!(errors.iter().any(|e| e.starts_with("spawns.weights:")));
+        assert!(errors.iter().any(|e| e.starts_with("spawns.initial:")));
+    }
+}
@@ -0,0 +1,344 @@
+use crate::{App, FIELD_SIZES, Screen, WIN_VALUES};
+use crate::keymap::Preset;
+use crate::movement::{MergeRule, TRIPLE_WIN_VALUES};
+use crate::record::{BLOCKER_COUNTS, MOVE_LIMITS, TIME_LIMITS, TRIPLE_SPAWN_VALUES, Variant};
+use crate::util::{format_duration, SpawnPosition};
+
+// a row of a settings menu, declared once and used for both rendering and input
+pub struct MenuOption {
+    pub name: &'static str,
+    // top level key of the config file that is written when the option changes, if any
+    pub key: Option<&'static str>,
+    pub setting: Setting,
+}
+
+pub enum Setting {
+    Toggle {
+        get: fn(&App) -> bool,
+        set: fn(&mut App, bool),
+    },
+    // one of a fixed list of values, stored as its index
+    Choice {
+        values: fn() -> Vec<String>,
+        get: fn(&App) -> usize,
+        set: fn(&mut App, usize),
+    },
+    Range {
+        min: u64,
+        max: u64,
+        get: fn(&App) -> u64,
+        set: fn(&mut App, u64),
+    },
+    Submenu(Screen),
+}
+
+impl Setting {
+    // steps to the next or previous value, lists and ranges stop at their ends
+    pub fn change(&self, app: &mut App, forward: bool) {
+        match self {
+            Setting::Toggle { get, set } => set(app, !get(app)),
+            Setting::Choice { values, get, set } => {
+                let index = get(app);
+                set(app, if forward { (index + 1).min(values().len() - 1) } else { index.saturating_sub(1) });
+            }
+            Setting::Range { min, max, get, set } => {
+                let value = get(app);
+                set(app, if forward { (value + 1).min(*max) } else { value.saturating_sub(1).max(*min) });
+            }
+            Setting::Submenu(_) => {}
+        }
+    }
+}
+
+pub fn config_options() -> Vec<MenuOption> {
+    vec![
+        MenuOption {
+            name: "Control Mapping",
+            key: None,
+            setting: Setting::Submenu(Screen::Keymap),
+        },
+        MenuOption {
+            name: "Key Preset",
+            key: Some("keymap"),
+            setting: Setting::Choice {
+                values: || Preset::ALL.iter().map(|p| p.name().to_string()).collect(),
+                get: |app| Preset::ALL.iter().position(|p| *p == app.config.keymap.preset).unwrap_or(0),
+                set: |app, i| app.config.keymap.set_preset(Preset::ALL[i]),
+            },
+        },
+        MenuOption {
+            name: "Color Scheme",
+            key: None,
+            setting: Setting::Submenu(Screen::Colors),
+        },
+        field_size(),
+        win_value(),
+        MenuOption {
+            name: "Spawn Position",
+            key: Some("spawns"),
+            setting: Setting::Choice {
+                values: || SpawnPosition::ALL.iter().map(|p| p.name().to_string()).collect(),
+                get: |app| SpawnPosition::ALL.iter().position(|p| *p == app.config.spawns.position).unwrap_or(0),
+                set: |app, i| app.config.spawns.position = SpawnPosition::ALL[i],
+            },
+        },
+        MenuOption {
+            name: "Tiles per Move",
+            key: Some("spawns"),
+            setting: Setting::Range {
+                min: 1,
+                max: 4,
+                get: |app| app.config.spawns.per_move as u64,
+                set: |app, value| app.config.spawns.per_move = value as usize,
+            },
+        },
+        MenuOption {
+            name: "Show Reset Popup",
+            key: Some("reset_popup"),
+            setting: Setting::Toggle {
+                get: |app| app.config.reset_popup,
+                set: |app, value| app.config.reset_popup = value,
+            },
+        },
+        MenuOption {
+            name: "Win/Loss Animation",
+            key: Some("ending_animation"),
+            setting: Setting::Toggle {
+                get: |app| app.config.ending_animation,
+                set: |app, value| app.config.ending_animation = value,
+            },
+        },
+        MenuOption {
+            name: "Show Control Info",
+            key: Some("control_info"),
+            setting: Setting::Toggle {
+                get: |app| app.config.control_info,
+                set: |app, value| app.config.control_info = value,
+            },
+        },
+        MenuOption {
+            name: "Mouse Drag Distance",
+            key: Some("mouse"),
+            setting: Setting::Range {
+                min: 1,
+                max: 20,
+                get: |app| app.config.mouse.drag_distance as u64,
+                set: |app, value| app.config.mouse.drag_distance = value as u16,
+            },
+        },
+        // not part of the config, decides whether the others are written to it
+        MenuOption {
+            name: "Apply Changes",
+            key: None,
+            setting: Setting::Choice {
+                values: || vec![String::from("permanently"), String::from("this session")],
+                get: |app| app.session_only as usize,
+                set: |app, i| app.session_only = i == 1,
+            },
+        },
+    ]
+}
+
+// the new game screen only changes the settings for the next game, it only shows what the chosen mode uses
+pub fn new_game_options(variant: Variant) -> Vec<MenuOption> {
+    let mut options = vec![
+        MenuOption {
+            name: "Mode",
+            key: None,
+            setting: Setting::Choice {
+                values: || Variant::MODES.iter().map(|v| v.name().to_string()).collect(),
+                get: |app| Variant::MODES.iter().position(|v| *v == app.variant).unwrap_or(0),
+                set: |app, i| app.variant = Variant::MODES[i],
+            },
+        },
+        field_size(),
+    ];
+
+    match variant {
+        Variant::Classic | Variant::Daily | Variant::Puzzle | Variant::Sandbox => options.push(win_value()),
+        Variant::Blitz => options.push(MenuOption {
+            name: "Time Limit",
+            key: None,
+            setting: Setting::Choice {
+                values: || TIME_LIMITS.iter().map(|limit| format_duration(*limit)).collect(),
+                get: |app| app.time_limit,
+                set: |app, i| app.time_limit = i,
+            },
+        }),
+        Variant::LimitedMoves => options.extend([
+            win_value(),
+            MenuOption {
+                name: "Move Limit",
+                key: None,
+                setting: Setting::Choice {
+                    values: || MOVE_LIMITS.iter().map(|limit| limit.to_string()).collect(),
+                    get: |app| app.move_limit,
+                    set: |app, i| app.move_limit = i,
+                },
+            },
+        ]),
+        // the win values depend on the merge rule, the index into them is the same
+        Variant::Fibonacci => options.push(MenuOption {
+            name: "Win Value",
+            key: Some("win_value"),
+            setting: Setting::Choice {
+                values: || MergeRule::Fibonacci.win_values().iter().map(|value| value.to_string()).collect(),
+                get: |app| app.config.win_value,
+                set: |app, i| app.config.win_value = i,
+            },
+        }),
+        Variant::Triples => options.extend([
+            MenuOption {
+                name: "Win Value",
+                key: None,
+                setting: Setting::Choice {
+                    values: || TRIPLE_WIN_VALUES.iter().map(|value| value.to_string()).collect(),
+                    get: |app| app.triple_win_value,
+                    set: |app, i| app.triple_win_value = i,
+                },
+            },
+            MenuOption {
+                name: "Spawn Tiles",
+                key: None,
+                setting: Setting::Choice {
+                    values: || TRIPLE_SPAWN_VALUES.iter().map(|(common, rare)| if common == rare { format!("{common}") } else { format!("{common}, sometimes {rare}") }).collect(),
+                    get: |app| app.triple_spawns,
+                    set: |app, i| app.triple_spawns = i,
+                },
+            },
+        ]),
+        Variant::Blockers => options.extend([
+            win_value(),
+            MenuOption {
+                name: "Blockers",
+                key: None,
+                setting: Setting::Choice {
+                    values: || BLOCKER_COUNTS.iter().map(|count| count.to_string()).collect(),
+                    get: |app| app.blockers,
+                    set: |app, i| app.blockers = i,
+                },
+            },
+        ]),
+    }
+    options
+}
+
+// the board itself is changed in the editor, resizing keeps the tiles that still fit
+pub fn sandbox_options() -> Vec<MenuOption> {
+    vec![
+        MenuOption {
+            name: "Field Size",
+            key: None,
+            setting: Setting::Choice {
+                values: || FIELD_SIZES.iter().map(|size| format!("{size}x{size}")).collect(),
+                get: |app| FIELD_SIZES.iter().position(|size| *size as usize == app.editor.len()).unwrap_or(0),
+                set: |app, i| app.resize_editor(FIELD_SIZES[i] as usize),
+            },
+        },
+        MenuOption {
+            name: "Spawn Tiles",
+            key: None,
+            setting: Setting::Toggle {
+                get: |app| app.sandbox_spawns,
+                set: |app, value| app.sandbox_spawns = value,
+            },
+        },
+        MenuOption {
+            name: "Board",
+            key: None,
+            setting: Setting::Submenu(Screen::Editor),
+        },
+    ]
+}
+
+fn field_size() -> MenuOption {
+    MenuOption {
+        name: "Field Size",
+        key: Some("field_size"),
+        setting: Setting::Choice {
+            values: || FIELD_SIZES.iter().map(|size| format!("{size}x{size}")).collect(),
+            get: |app| app.config.field_size,
+            set: |app, i| app.config.field_size = i,
+        },
+    }
+}
+
+fn win_value() -> MenuOption {
+    MenuOption {
+        name: "Win Value",
+        key: Some("win_value"),
+        setting: Setting::Choice {
+            values: || WIN_VALUES.iter().map(|value| value.to_string()).collect(),
+            get: |app| app.config.win_value,
+            set: |app, i| app.config.win_value = i,
+        },
+    }
+}
+
+#[cfg(test)]
+mod menu_test {
+    use crate::{App, Config, FIELD_SIZES, Screen};
+    use super::{config_options, Setting};
+
+    fn select(app: &mut App, name: &str) {
+        let row = config_options().iter().position(|option| option.name == name).unwrap();
+        app.tablestate.select(Some(row));
+    }
+
+    #[test]
+    fn test_submenu_opens_screen() {
+        let mut app = App::new(Config::default(), Some(1));
+        app.config();
+        select(&mut app, "Color Scheme");
+        app.confirm();
+        assert_eq!(Screen::Colors, app.screen());
+    }
+
+    #[test]
+    fn test_keymap_is_read_only() {
+        let mut app = App::new(Config::default(), Some(1));
+        app.config();
+        select(&mut app, "Control Mapping");
+        app.confirm();
+        assert_eq!(Screen::Keymap, app.screen());
+        app.confirm();
+        assert_eq!(Screen::Config, app.screen());
+    }
+
+    #[test]
+    fn test_choice_stops_at_the_ends() {
+        let mut app = App::new(Config::default(), Some(1));
+        app.config();
+        select(&mut app, "Field Size");
+        app.confirm();
+        for _ in 0..FIELD_SIZES.len() + 1 {
+            app.right();
+        }
+        assert_eq!(FIELD_SIZES.len() - 1, app.config.field_size);
+        for _ in 0..FIELD_SIZES.len() + 1 {
+            app.left();
+        }
+        assert_eq!(0, app.config.field_size);
+    }
+
+    #[test]
+    fn test_toggle_and_range() {
+        let mut app = App::new(Config::default(), Some(1));
+        for option in config_options() {
+            match option.setting {
+                Setting::Toggle { get, .. } => {
+                    let before = get(&app);
+                    option.setting.change(&mut app, true);
+                    assert_ne!(before, get(&app), "{}", option.name);
+                }
+                Setting::Range { min, get, .. } => {
+                    for _ in 0..30 {
+                        option.setting.change(&mut app, false);
+                    }
+                    assert_eq!(min, get(&app), "{}", option.name);
+                }
+                _ => {}
+            }
+        }
+    }
+}
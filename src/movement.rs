@@ -1,11 +1,101 @@
 use itertools::Itertools;
 
-use crate::Data;
+use crate::{Data, WIN_VALUES};
+
+// a field that tiles can't move through or merge with, it never moves itself
+pub const BLOCKER: u32 = u32::MAX;
+
+// the win values of the fibonacci rule, about as hard to reach as the ones of the classic rule
+const FIBONACCI_WIN_VALUES: [u32; 12] = [8, 13, 34, 55, 144, 233, 610, 987, 1597, 4181, 6765, 17711];
+// powers of three are too far apart to line up with the other win values, so triples have their own choice
+pub const TRIPLE_WIN_VALUES: [u32; 7] = [27, 81, 243, 729, 2187, 6561, 19683];
+
+// decides which tiles merge and what they become, everything that depends on the tile values asks the rule
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MergeRule {
+    // two equal tiles merge into their sum
+    #[default]
+    Doubling,
+    // two neighbouring fibonacci numbers merge into the next one
+    Fibonacci,
+    // three equal tiles in a row merge into their sum
+    Triples,
+}
+
+impl MergeRule {
+    // how many tiles merge at once
+    pub fn group(&self) -> usize {
+        match self {
+            MergeRule::Doubling | MergeRule::Fibonacci => 2,
+            MergeRule::Triples => 3,
+        }
+    }
+
+    // the tile that a group of neighbouring tiles merges into, if it merges at all
+    pub fn merge(&self, tiles: &[u32]) -> Option<u32> {
+        if tiles.len() != self.group() || tiles.contains(&0) {
+            return None;
+        }
+        match self {
+            MergeRule::Doubling | MergeRule::Triples => tiles.iter().all_equal().then(|| tiles.iter().sum()),
+            // the sum of two fibonacci numbers is only one itself if they are neighbours
+            MergeRule::Fibonacci => tiles[0].checked_add(tiles[1])
+                .filter(|sum| is_fibonacci(tiles[0]) && is_fibonacci(tiles[1]) && is_fibonacci(*sum)),
+        }
+    }
+
+    // the tile that usually spawns and the one that spawns once in a while
+    pub fn spawn_values(&self) -> (u32, u32) {
+        match self {
+            MergeRule::Doubling => (2, 4),
+            MergeRule::Fibonacci => (1, 2),
+            MergeRule::Triples => (3, 9),
+        }
+    }
+
+    // indices into the first two are stored in the config, so they have the same length
+    pub fn win_values(&self) -> &'static [u32] {
+        match self {
+            MergeRule::Doubling => &WIN_VALUES,
+            MergeRule::Fibonacci => &FIBONACCI_WIN_VALUES,
+            MergeRule::Triples => &TRIPLE_WIN_VALUES,
+        }
+    }
+
+    // the win value that is chosen by default, the top of the color scale
+    fn default_win_value(&self) -> u32 {
+        match self {
+            MergeRule::Doubling => 2048,
+            MergeRule::Fibonacci => 1597,
+            MergeRule::Triples => 2187,
+        }
+    }
+
+    // how far up the color scale the tile is, from 0 for the smallest tile to 1 for the default win value and above
+    pub fn progress(&self, value: u32) -> f32 {
+        let steps = |value: u32| match self {
+            MergeRule::Doubling => value.ilog2().max(1) - 1,
+            MergeRule::Fibonacci => fibonacci().take_while(|f| *f < value).count() as u32,
+            MergeRule::Triples => value.ilog(3).max(1) - 1,
+        };
+        (steps(value) as f32 / steps(self.default_win_value()) as f32).min(1.0)
+    }
+}
+
+// 1, 2, 3, 5, 8, ... without the second 1, since there is only one tile of each value
+fn fibonacci() -> impl Iterator<Item=u32> {
+    std::iter::successors(Some((1u32, 2u32)), |(a, b)| a.checked_add(*b).map(|sum| (*b, sum))).map(|(a, _)| a)
+}
+
+fn is_fibonacci(value: u32) -> bool {
+    fibonacci().take_while(|f| *f <= value).any(|f| f == value)
+}
 
-fn merge_backward(slice: &mut [u32]) {
-    if slice[0] == slice[1] && slice[1] != 0 {
-        slice[0] = 0;
-        slice[1] += slice[1];
+// merges the group into its last field
+fn merge_backward(slice: &mut [u32], rule: MergeRule) {
+    if let Some(merged) = rule.merge(slice) {
+        slice.fill(0);
+        slice[slice.len() - 1] = merged;
     }
 }
 
@@ -20,19 +110,32 @@ fn stable_partition<T, I, F>(slice: I, pred: F) -> Vec<T>
     left
 }
 
-pub fn slide_right(data: &[u32]) -> Vec<u32> {
+// blockers split the row into parts that slide on their own
+pub fn slide_right(data: &[u32], rule: MergeRule) -> Vec<u32> {
+    let mut ret = Vec::with_capacity(data.len());
+    for (i, segment) in data.split(|x| *x == BLOCKER).enumerate() {
+        if i > 0 {
+            ret.push(BLOCKER);
+        }
+        ret.extend(slide_segment_right(segment, rule));
+    }
+    ret
+}
+
+fn slide_segment_right(data: &[u32], rule: MergeRule) -> Vec<u32> {
     let mut ret = stable_partition(data.iter().copied(), |x| *x == 0);
+    let group = rule.group();
     let mut index = data.len();
-    while index > 1 {
-        merge_backward(&mut ret[index - 2..index]);
+    while index >= group {
+        merge_backward(&mut ret[index - group..index], rule);
         index -= 1;
     }
     stable_partition(ret.iter().copied(), |x| *x == 0)
 }
 
-pub fn slide_left(data: &[u32]) -> Vec<u32> {
+pub fn slide_left(data: &[u32], rule: MergeRule) -> Vec<u32> {
     let ret = data.iter().rev().copied().collect_vec();
-    let data = slide_right(&ret);
+    let data = slide_right(&ret, rule);
     data.iter().rev().copied().collect_vec()
 }
 
@@ -56,81 +159,131 @@ pub fn rotate(matrix: &mut [Data], counter_clockwise: bool) {
 
 #[cfg(test)]
 mod slide_test {
+    use super::{BLOCKER, MergeRule};
     use super::slide_left;
     use super::slide_right;
 
     #[test]
     fn test_slide_right_with_one_element() {
-        assert_eq!(vec![0, 0, 0, 1], slide_right(&[0, 1, 0, 0]));
+        assert_eq!(vec![0, 0, 0, 1], slide_right(&[0, 1, 0, 0], MergeRule::Doubling));
     }
 
     #[test]
     fn test_slide_left_with_one_element() {
-        assert_eq!(vec![1, 0, 0, 0], slide_left(&[0, 1, 0, 0]));
+        assert_eq!(vec![1, 0, 0, 0], slide_left(&[0, 1, 0, 0], MergeRule::Doubling));
     }
 
     #[test]
     fn test_slide_right_with_two_different_elements() {
-        assert_eq!(vec![0, 0, 1, 2], slide_right(&[1, 0, 2, 0]));
+        assert_eq!(vec![0, 0, 1, 2], slide_right(&[1, 0, 2, 0], MergeRule::Doubling));
     }
 
     #[test]
     fn test_slide_left_with_two_different_elements() {
-        assert_eq!(vec![1, 2, 0, 0], slide_left(&[1, 0, 2, 0]));
+        assert_eq!(vec![1, 2, 0, 0], slide_left(&[1, 0, 2, 0], MergeRule::Doubling));
     }
 
     #[test]
     fn test_slide_right_with_two_same_elements() {
-        assert_eq!(vec![0, 0, 0, 2], slide_right(&[1, 0, 1, 0]));
+        assert_eq!(vec![0, 0, 0, 2], slide_right(&[1, 0, 1, 0], MergeRule::Doubling));
     }
 
     #[test]
     fn test_slide_left_with_two_same_elements() {
-        assert_eq!(vec![2, 0, 0, 0], slide_left(&[1, 0, 1, 0]));
+        assert_eq!(vec![2, 0, 0, 0], slide_left(&[1, 0, 1, 0], MergeRule::Doubling));
     }
 
     #[test]
     fn test_slide_right_with_three_same_elements() {
-        assert_eq!(vec![0, 0, 1, 2], slide_right(&[1, 0, 1, 1]));
+        assert_eq!(vec![0, 0, 1, 2], slide_right(&[1, 0, 1, 1], MergeRule::Doubling));
     }
 
     #[test]
     fn test_slide_left_with_three_same_elements() {
-        assert_eq!(vec![2, 1, 0, 0], slide_left(&[1, 0, 1, 1]));
+        assert_eq!(vec![2, 1, 0, 0], slide_left(&[1, 0, 1, 1], MergeRule::Doubling));
     }
 
     #[test]
     fn test_slide_right_with_three_different_elements() {
-        assert_eq!(vec![0, 0, 2, 2], slide_right(&[1, 0, 1, 2]));
-        assert_eq!(vec![0, 2, 1, 2], slide_right(&[2, 0, 1, 2]));
-        assert_eq!(vec![0, 0, 2, 2], slide_right(&[0, 1, 1, 2]));
+        assert_eq!(vec![0, 0, 2, 2], slide_right(&[1, 0, 1, 2], MergeRule::Doubling));
+        assert_eq!(vec![0, 2, 1, 2], slide_right(&[2, 0, 1, 2], MergeRule::Doubling));
+        assert_eq!(vec![0, 0, 2, 2], slide_right(&[0, 1, 1, 2], MergeRule::Doubling));
     }
 
     #[test]
     fn test_slide_left_with_three_different_elements() {
-        assert_eq!(vec![2, 2, 0, 0], slide_left(&[1, 0, 1, 2]));
-        assert_eq!(vec![2, 1, 2, 0], slide_left(&[2, 0, 1, 2]));
-        assert_eq!(vec![2, 2, 0, 0], slide_left(&[0, 1, 1, 2]));
+        assert_eq!(vec![2, 2, 0, 0], slide_left(&[1, 0, 1, 2], MergeRule::Doubling));
+        assert_eq!(vec![2, 1, 2, 0], slide_left(&[2, 0, 1, 2], MergeRule::Doubling));
+        assert_eq!(vec![2, 2, 0, 0], slide_left(&[0, 1, 1, 2], MergeRule::Doubling));
     }
 
     #[test]
     fn test_slide_right_with_four_same_elements() {
-        assert_eq!(vec![0, 0, 2, 2], slide_right(&[1, 1, 1, 1]));
+        assert_eq!(vec![0, 0, 2, 2], slide_right(&[1, 1, 1, 1], MergeRule::Doubling));
     }
 
     #[test]
     fn test_slide_left_with_four_same_elements() {
-        assert_eq!(vec![2, 2, 0, 0], slide_left(&[1, 1, 1, 1]));
+        assert_eq!(vec![2, 2, 0, 0], slide_left(&[1, 1, 1, 1], MergeRule::Doubling));
     }
 
     #[test]
     fn test_slide_right_with_four_different_elements() {
-        assert_eq!(vec![1, 2, 1, 2], slide_right(&[1, 2, 1, 2]));
+        assert_eq!(vec![1, 2, 1, 2], slide_right(&[1, 2, 1, 2], MergeRule::Doubling));
     }
 
     #[test]
     fn test_slide_left_with_four_different_elements() {
-        assert_eq!(vec![1, 2, 1, 2], slide_left(&[1, 2, 1, 2]));
+        assert_eq!(vec![1, 2, 1, 2], slide_left(&[1, 2, 1, 2], MergeRule::Doubling));
+    }
+
+    #[test]
+    fn test_slide_fibonacci() {
+        assert_eq!(vec![0, 0, 0, 3], slide_right(&[1, 0, 2, 0], MergeRule::Fibonacci));
+        assert_eq!(vec![0, 0, 2, 5], slide_right(&[1, 1, 2, 3], MergeRule::Fibonacci));
+        assert_eq!(vec![2, 5, 0, 0], slide_left(&[1, 1, 2, 3], MergeRule::Fibonacci));
+        assert_eq!(vec![0, 2, 2, 5], slide_right(&[2, 2, 5, 0], MergeRule::Fibonacci));
+        assert_eq!(vec![0, 1, 3, 8], slide_right(&[1, 3, 8, 0], MergeRule::Fibonacci));
+    }
+
+    #[test]
+    fn test_fibonacci_merges() {
+        assert_eq!(Some(2), MergeRule::Fibonacci.merge(&[1, 1]));
+        assert_eq!(Some(89), MergeRule::Fibonacci.merge(&[55, 34]));
+        assert_eq!(None, MergeRule::Fibonacci.merge(&[13, 55]));
+        assert_eq!(None, MergeRule::Fibonacci.merge(&[4, 4]));
+        assert_eq!(None, MergeRule::Doubling.merge(&[2, 3]));
+    }
+
+    #[test]
+    fn test_slide_triples() {
+        assert_eq!(vec![0, 0, 0, 9], slide_right(&[3, 3, 0, 3], MergeRule::Triples));
+        assert_eq!(vec![0, 0, 3, 9], slide_right(&[3, 3, 3, 3], MergeRule::Triples));
+        assert_eq!(vec![9, 3, 0, 0], slide_left(&[3, 3, 3, 3], MergeRule::Triples));
+        assert_eq!(vec![0, 0, 3, 3], slide_right(&[3, 0, 3, 0], MergeRule::Triples));
+        assert_eq!(vec![0, 9, 3, 3], slide_right(&[9, 3, 3, 0], MergeRule::Triples));
+        assert_eq!(vec![0, 0, 27, 3, 9], slide_right(&[9, 9, 9, 3, 9], MergeRule::Triples));
+    }
+
+    #[test]
+    fn test_progress() {
+        for rule in [MergeRule::Doubling, MergeRule::Fibonacci, MergeRule::Triples] {
+            assert_eq!(0.0, rule.progress(rule.spawn_values().0));
+            assert_eq!(1.0, rule.progress(rule.default_win_value()));
+            assert_eq!(1.0, rule.progress(*rule.win_values().last().unwrap()));
+        }
+    }
+
+    #[test]
+    fn test_slide_right_with_blocker() {
+        assert_eq!(vec![0, 1, BLOCKER, 0, 2], slide_right(&[1, 0, BLOCKER, 1, 1], MergeRule::Doubling));
+        assert_eq!(vec![BLOCKER, BLOCKER, 0, 1], slide_right(&[BLOCKER, BLOCKER, 1, 0], MergeRule::Doubling));
+    }
+
+    #[test]
+    fn test_slide_left_with_blocker() {
+        assert_eq!(vec![1, 0, BLOCKER, 2, 0], slide_left(&[0, 1, BLOCKER, 1, 1], MergeRule::Doubling));
+        assert_eq!(vec![1, BLOCKER, 1, 0, BLOCKER], slide_left(&[1, BLOCKER, 0, 1, BLOCKER], MergeRule::Doubling));
     }
 }
 
@@ -0,0 +1,84 @@
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const APP_DIR: &str = "tui-2048";
+const CONFIG_FILE: &str = "config.json";
+
+// config files, overridable with TUI_2048_CONFIG_DIR
+pub fn config_dir() -> PathBuf {
+    resolve(env::var_os("TUI_2048_CONFIG_DIR"), env::var_os("XDG_CONFIG_HOME"), env::var_os("HOME"), ".config")
+}
+
+// saves, history and replays, overridable with TUI_2048_DATA_DIR
+pub fn data_dir() -> PathBuf {
+    resolve(env::var_os("TUI_2048_DATA_DIR"), env::var_os("XDG_DATA_HOME"), env::var_os("HOME"), ".local/share")
+}
+
+pub fn config_file() -> PathBuf {
+    config_dir().join(CONFIG_FILE)
+}
+
+fn resolve(dir_override: Option<OsString>, xdg: Option<OsString>, home: Option<OsString>, home_fallback: &str) -> PathBuf {
+    if let Some(dir) = dir_override.filter(|d| !d.is_empty()) {
+        return PathBuf::from(dir);
+    }
+
+    // the XDG spec says relative paths have to be ignored
+    match xdg.map(PathBuf::from).filter(|d| d.is_absolute()) {
+        Some(xdg) => xdg.join(APP_DIR),
+        None => match home.filter(|h| !h.is_empty()) {
+            Some(home) => Path::new(&home).join(home_fallback).join(APP_DIR),
+            None => PathBuf::from("."),
+        }
+    }
+}
+
+// older versions created config.json in the working directory, it is moved once if there is no config yet
+pub fn migrate_legacy_config(config: &Path) -> io::Result<bool> {
+    let legacy = Path::new(CONFIG_FILE);
+    if !legacy.is_file() || config.exists() {
+        return Ok(false);
+    }
+
+    if let Some(parent) = config.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // renaming fails across file systems
+    if fs::rename(legacy, config).is_err() {
+        fs::copy(legacy, config)?;
+        fs::remove_file(legacy)?;
+    }
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod resolve_test {
+    use std::path::PathBuf;
+    use super::resolve;
+
+    #[test]
+    fn test_resolve_override() {
+        assert_eq!(PathBuf::from("/tmp/2048"), resolve(Some("/tmp/2048".into()), Some("/xdg".into()), Some("/home/user".into()), ".config"));
+    }
+
+    #[test]
+    fn test_resolve_xdg() {
+        assert_eq!(PathBuf::from("/xdg/tui-2048"), resolve(None, Some("/xdg".into()), Some("/home/user".into()), ".config"));
+        assert_eq!(PathBuf::from("/xdg/tui-2048"), resolve(Some("".into()), Some("/xdg".into()), None, ".config"));
+    }
+
+    #[test]
+    fn test_resolve_home_fallback() {
+        assert_eq!(PathBuf::from("/home/user/.local/share/tui-2048"), resolve(None, None, Some("/home/user".into()), ".local/share"));
+        assert_eq!(PathBuf::from("/home/user/.config/tui-2048"), resolve(None, Some("relative".into()), Some("/home/user".into()), ".config"));
+    }
+
+    #[test]
+    fn test_resolve_working_directory() {
+        assert_eq!(PathBuf::from("."), resolve(None, None, None, ".config"));
+    }
+}
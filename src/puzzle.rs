@@ -0,0 +1,234 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Data, FIELD_SIZES, paths};
+use crate::movement::MergeRule;
+use crate::settings::write_atomic;
+use crate::util::{check_win, max_tile, spawn_field, SpawnRules, tile_count};
+
+const PUZZLE_DIR: &str = "puzzles";
+const SOLVED_FILE: &str = "solved_puzzles.json";
+// shipped with the game, more packs can be put into the puzzle directory
+const BUILTIN_PACK: &str = include_str!("../puzzles/basics.json");
+
+// a json file with a list of puzzles, its name is shown in the browser
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PuzzlePack {
+    pub name: String,
+    pub puzzles: Vec<Puzzle>,
+}
+
+// a puzzle is stored in every save and replay of it, so they still work when the pack changes
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Puzzle {
+    pub name: String,
+    // filled in when the pack is loaded, it is part of the id that marks the puzzle as solved
+    #[serde(default)]
+    pub pack: String,
+    pub board: Vec<Vec<u32>>,
+    // without spawns the usual random tiles appear, otherwise exactly these and nothing else
+    #[serde(default)]
+    pub spawns: Option<Vec<Spawn>>,
+    pub goal: Goal,
+    #[serde(default)]
+    pub moves: Option<usize>,
+}
+
+// the tile that appears after the move with the same index, counted from 0
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Spawn {
+    pub row: usize,
+    pub column: usize,
+    pub value: u32,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Goal {
+    Reach { tile: u32 },
+    // every tile has to be merged into one
+    SingleTile,
+}
+
+impl Goal {
+    pub fn reached(&self, field: &[Data]) -> bool {
+        match self {
+            Goal::Reach { tile } => check_win(field, tile),
+            Goal::SingleTile => tile_count(field) == 1,
+        }
+    }
+}
+
+impl Puzzle {
+    pub fn id(&self) -> String {
+        format!("{}/{}", self.pack, self.name)
+    }
+
+    pub fn field(&self) -> Vec<Data> {
+        self.board.iter().map(|row| Data { numbers: row.clone() }).collect()
+    }
+
+    // "reach 512 in 20 moves", for the browser and the title
+    pub fn describe(&self) -> String {
+        let goal = match self.goal {
+            Goal::Reach { tile } => format!("reach {tile}"),
+            Goal::SingleTile => String::from("clear to a single tile"),
+        };
+        match self.moves {
+            Some(moves) => format!("{goal} in {moves} moves"),
+            None => goal,
+        }
+    }
+
+    // spawns the tile for the given number of moves, fixed spawns on fields that are taken are left out
+    // puzzles are always played with the classic rules
+    pub fn spawn(&self, field: &mut [Data], moves: usize) {
+        match &self.spawns {
+            None => spawn_field(field, &SpawnRules::default(), MergeRule::Doubling, None),
+            Some(spawns) => {
+                if let Some(spawn) = spawns.get(moves) {
+                    let value = &mut field[spawn.row].numbers[spawn.column];
+                    if *value == 0 {
+                        *value = spawn.value;
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let size = self.board.len();
+        if !FIELD_SIZES.contains(&(size as u16)) || self.board.iter().any(|row| row.len() != size) {
+            return Err(String::from("the board has to be square with 3 to 9 rows"));
+        }
+        if self.board.iter().flatten().any(|value| *value != 0 && !is_tile(*value)) {
+            return Err(String::from("tiles have to be powers of two or 0 for empty fields"));
+        }
+        if let Some(spawn) = self.spawns.iter().flatten().find(|s| s.row >= size || s.column >= size || !is_tile(s.value)) {
+            return Err(format!("the spawn of {} at row {}, column {} is not on the board or not a power of two", spawn.value, spawn.row, spawn.column));
+        }
+        if let Goal::Reach { tile } = self.goal {
+            if !is_tile(tile) || tile <= max_tile(&self.field()) {
+                return Err(format!("the goal {tile} has to be a power of two above the highest tile"));
+            }
+        }
+        if self.moves == Some(0) {
+            return Err(String::from("the move limit has to be at least 1"));
+        }
+        Ok(())
+    }
+}
+
+fn is_tile(value: u32) -> bool {
+    value >= 2 && value.is_power_of_two()
+}
+
+fn parse_pack(json: &str) -> Result<PuzzlePack, String> {
+    let mut pack: PuzzlePack = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    for puzzle in pack.puzzles.iter_mut() {
+        puzzle.validate().map_err(|e| format!("puzzle \"{}\": {e}", puzzle.name))?;
+        puzzle.pack = pack.name.clone();
+    }
+    Ok(pack)
+}
+
+// the built-in pack first, then the ones in the puzzle directory sorted by file name
+// packs that can't be read are left out and returned as errors
+pub fn load_packs() -> (Vec<PuzzlePack>, Vec<String>) {
+    let mut packs = vec![parse_pack(BUILTIN_PACK).expect("the built-in puzzle pack is valid")];
+    let mut errors = Vec::new();
+
+    let dir = paths::data_dir().join(PUZZLE_DIR);
+    let mut files = fs::read_dir(&dir)
+        .map(|entries| entries.filter_map(|entry| entry.ok().map(|e| e.path())).collect::<Vec<_>>())
+        .unwrap_or_default();
+    files.retain(|path| path.extension().is_some_and(|e| e == "json"));
+    files.sort();
+
+    for path in files {
+        match fs::read_to_string(&path).map_err(|e| e.to_string()).and_then(|json| parse_pack(&json)) {
+            Ok(pack) => packs.push(pack),
+            Err(e) => errors.push(format!("Could not load the puzzle pack {}: {e}", path.display())),
+        }
+    }
+    (packs, errors)
+}
+
+// the ids of all solved puzzles
+pub fn read_solved() -> BTreeSet<String> {
+    fs::read(paths::data_dir().join(SOLVED_FILE))
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+pub fn mark_solved(puzzle: &Puzzle) -> io::Result<()> {
+    let mut solved = read_solved();
+    if solved.insert(puzzle.id()) {
+        fs::create_dir_all(paths::data_dir())?;
+        write_atomic(&paths::data_dir().join(SOLVED_FILE), &serde_json::to_vec(&solved)?)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod puzzle_test {
+    use crate::Data;
+    use super::{BUILTIN_PACK, Goal, parse_pack, Spawn};
+
+    const PACK: &str = r#"{
+        "name": "Test",
+        "puzzles": [{
+            "name": "Mixed",
+            "board": [[16, 128, 32, 4], [4, 2, 8, 2], [0, 0, 0, 2], [2, 0, 0, 0]],
+            "spawns": [{"row": 3, "column": 3, "value": 4}],
+            "goal": {"type": "reach", "tile": 512},
+            "moves": 20
+        }]
+    }"#;
+
+    #[test]
+    fn test_parse_pack() {
+        let pack = parse_pack(PACK).unwrap();
+        let puzzle = &pack.puzzles[0];
+        assert_eq!("Test/Mixed", puzzle.id());
+        assert_eq!(Goal::Reach { tile: 512 }, puzzle.goal);
+        assert_eq!(Some(vec![Spawn { row: 3, column: 3, value: 4 }]), puzzle.spawns);
+        assert_eq!("reach 512 in 20 moves", puzzle.describe());
+
+        assert!(parse_pack(BUILTIN_PACK).is_ok());
+    }
+
+    #[test]
+    fn test_invalid_puzzles() {
+        assert!(parse_pack(&PACK.replace("[2, 0, 0, 0]", "[2, 0, 0]")).is_err());
+        assert!(parse_pack(&PACK.replace("[2, 0, 0, 0]", "[3, 0, 0, 0]")).is_err());
+        assert!(parse_pack(&PACK.replace("\"row\": 3", "\"row\": 4")).is_err());
+        assert!(parse_pack(&PACK.replace("512", "128")).is_err());
+        assert!(parse_pack(&PACK.replace("20", "0")).is_err());
+    }
+
+    #[test]
+    fn test_fixed_spawns() {
+        let puzzle = parse_pack(PACK).unwrap().puzzles.remove(0);
+        let mut field = puzzle.field();
+        puzzle.spawn(&mut field, 0);
+        assert_eq!(Data { numbers: vec![2, 0, 0, 4] }, field[3]);
+
+        // taken fields and moves without a spawn leave the board as it is
+        let before = field.clone();
+        puzzle.spawn(&mut field, 0);
+        puzzle.spawn(&mut field, 1);
+        assert_eq!(before, field);
+    }
+
+    #[test]
+    fn test_single_tile_goal() {
+        let goal = Goal::SingleTile;
+        assert!(!goal.reached(&[Data { numbers: vec![2, 2] }, Data { numbers: vec![0, 0] }]));
+        assert!(goal.reached(&[Data { numbers: vec![0, 4] }, Data { numbers: vec![0, 0] }]));
+    }
+}
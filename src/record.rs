@@ -0,0 +1,558 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::{Direction, FIELD_SIZES, paths};
+use crate::movement::MergeRule;
+use crate::puzzle::Puzzle;
+use crate::settings::write_atomic;
+use crate::util::{format_duration, SpawnRules};
+
+const SAVE_FILE: &str = "save.json";
+const HISTORY_FILE: &str = "history.json";
+const REPLAY_DIR: &str = "replays";
+// the date of the last official daily challenge that was started
+const DAILY_STARTED_FILE: &str = "daily_started";
+
+// everything needed to reproduce a game: the spawns are derived from the seed
+#[derive(Clone, Serialize, Deserialize)]
+pub struct GameRecord {
+    pub seed: u64,
+    pub field_size: u16,
+    pub win_value: u32,
+    pub moves: Vec<Direction>,
+    // time played, without pauses
+    #[serde(default)]
+    pub elapsed: Duration,
+    #[serde(default)]
+    pub variant: Variant,
+    #[serde(default)]
+    pub time_limit: Option<Duration>,
+    #[serde(default)]
+    pub move_limit: Option<usize>,
+    // the day of an official daily challenge attempt, practice runs don't have one
+    #[serde(default)]
+    pub date: Option<String>,
+    // puzzles start from their own board instead of two random tiles
+    #[serde(default)]
+    pub puzzle: Option<Puzzle>,
+    // sandbox games start from the board set up in the editor and can be played without new tiles
+    #[serde(default)]
+    pub board: Option<Vec<Vec<u32>>>,
+    #[serde(default)]
+    pub no_spawns: bool,
+    // fields that tiles can't pass, placed at random when the game starts
+    #[serde(default)]
+    pub blockers: usize,
+    // records without them, like daily challenges, spawn like the original game with the values of their merge rule
+    #[serde(default)]
+    pub spawns: Option<SpawnRules>,
+}
+
+impl GameRecord {
+    pub fn new(seed: u64, field_size: u16, win_value: u32) -> Self {
+        Self {
+            seed,
+            field_size,
+            win_value,
+            moves: Vec::new(),
+            elapsed: Duration::ZERO,
+            variant: Variant::Classic,
+            time_limit: None,
+            move_limit: None,
+            date: None,
+            puzzle: None,
+            board: None,
+            no_spawns: false,
+            blockers: 0,
+            spawns: None,
+        }
+    }
+
+    pub fn category(&self) -> Category {
+        Category { variant: self.variant, time_limit: self.time_limit, move_limit: self.move_limit }
+    }
+
+    pub fn spawn_rules(&self) -> SpawnRules {
+        self.spawns.clone().unwrap_or_else(|| SpawnRules::default().with_values(self.variant.merge_rule().spawn_values()))
+    }
+
+    // records can be edited by hand, so they are checked before a game is started from them
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let record: Self = serde_json::from_slice(&fs::read(path)?)?;
+        record.validate().map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(record)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if let Some(board) = &self.board {
+            let size = board.len();
+            if !FIELD_SIZES.contains(&(size as u16)) || board.iter().any(|row| row.len() != size) {
+                return Err(String::from("the board has to be square with 3 to 9 rows"));
+            }
+        }
+        if let Some(puzzle) = &self.puzzle {
+            puzzle.validate().map_err(|e| format!("puzzle \"{}\": {e}", puzzle.name))?;
+        }
+        if let Some(spawns) = &self.spawns {
+            spawns.validate(self.variant.merge_rule()).map_err(|e| format!("spawns.{e}"))?;
+        }
+        Ok(())
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        write_atomic(path, &serde_json::to_vec(self)?)
+    }
+}
+
+// the rules a game was played with, games of different variants aren't compared with each other
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Variant {
+    #[default]
+    Classic,
+    // as many points as possible before the time runs out, there is no win value
+    Blitz,
+    // the win value has to be reached with a limited number of moves
+    LimitedMoves,
+    // a classic game that is the same for everyone on the same day
+    Daily,
+    // a classic game with fields that tiles can't pass
+    Blockers,
+    // neighbouring fibonacci numbers merge instead of equal tiles
+    Fibonacci,
+    // three equal tiles in a row merge, the tiles are powers of three
+    Triples,
+    // a predefined board with its own goal, solved puzzles are tracked instead of adding them to the history
+    Puzzle,
+    // a board set up in the editor, there is nothing to win and it isn't added to the history either
+    Sandbox,
+}
+
+impl Variant {
+    // the ones that are found in the history
+    pub const ALL: [Variant; 7] = [Variant::Classic, Variant::Blitz, Variant::LimitedMoves, Variant::Daily, Variant::Blockers, Variant::Fibonacci, Variant::Triples];
+    // the ones that can be chosen for a new game, the others have their own screens
+    pub const MODES: [Variant; 6] = [Variant::Classic, Variant::Blitz, Variant::LimitedMoves, Variant::Blockers, Variant::Fibonacci, Variant::Triples];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Variant::Classic => "classic",
+            Variant::Blitz => "blitz",
+            Variant::LimitedMoves => "limited moves",
+            Variant::Daily => "daily",
+            Variant::Blockers => "blockers",
+            Variant::Fibonacci => "fibonacci",
+            Variant::Triples => "triples",
+            Variant::Puzzle => "puzzle",
+            Variant::Sandbox => "sandbox",
+        }
+    }
+
+    pub fn merge_rule(&self) -> MergeRule {
+        match self {
+            Variant::Fibonacci => MergeRule::Fibonacci,
+            Variant::Triples => MergeRule::Triples,
+            _ => MergeRule::Doubling,
+        }
+    }
+}
+
+// the time limits that can be chosen for blitz games
+pub const TIME_LIMITS: [Duration; 2] = [Duration::from_secs(120), Duration::from_secs(300)];
+// the move limits that can be chosen for limited moves games
+pub const MOVE_LIMITS: [usize; 4] = [50, 100, 200, 500];
+// the numbers of blockers that can be chosen
+pub const BLOCKER_COUNTS: [usize; 4] = [1, 2, 3, 4];
+// the spawn values that can be chosen for triples games, the first ones are the default of the merge rule
+pub const TRIPLE_SPAWN_VALUES: [(u32, u32); 3] = [(3, 9), (3, 3), (1, 3)];
+
+// a variant together with its limit, games are only compared within the same category
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Category {
+    pub variant: Variant,
+    pub time_limit: Option<Duration>,
+    pub move_limit: Option<usize>,
+}
+
+impl Category {
+    // the ones that are found in the history, every time and move limit is a category of its own
+    pub fn all() -> Vec<Category> {
+        Variant::ALL.iter().flat_map(|variant| {
+            let category = Category { variant: *variant, time_limit: None, move_limit: None };
+            match variant {
+                Variant::Blitz => TIME_LIMITS.iter().map(|limit| Category { time_limit: Some(*limit), ..category }).collect(),
+                Variant::LimitedMoves => MOVE_LIMITS.iter().map(|limit| Category { move_limit: Some(*limit), ..category }).collect(),
+                _ => vec![category],
+            }
+        }).collect()
+    }
+
+    // "blitz 2:00", "limited moves 100"
+    pub fn name(&self) -> String {
+        match (self.time_limit, self.move_limit) {
+            (Some(limit), _) => format!("{} {}", self.variant.name(), format_duration(limit)),
+            (_, Some(limit)) => format!("{} {limit}", self.variant.name()),
+            (None, None) => self.variant.name().to_string(),
+        }
+    }
+}
+
+// summary of a finished game, appended to the history
+// fields added later have defaults, so older histories can still be read
+#[derive(Clone, Serialize, Deserialize)]
+pub struct GameResult {
+    pub timestamp: u64,
+    pub field_size: u16,
+    pub win_value: u32,
+    pub score: u64,
+    pub max_tile: u32,
+    pub moves: usize,
+    pub won: bool,
+    #[serde(default)]
+    pub merges: usize,
+    #[serde(default)]
+    pub variant: Variant,
+    #[serde(default)]
+    pub date: Option<String>,
+    // older results don't have them, they only show up when all categories are shown
+    #[serde(default)]
+    pub time_limit: Option<Duration>,
+    #[serde(default)]
+    pub move_limit: Option<usize>,
+}
+
+impl GameResult {
+    pub fn category(&self) -> Category {
+        Category { variant: self.variant, time_limit: self.time_limit, move_limit: self.move_limit }
+    }
+}
+
+// only games matching all set fields are counted
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct StatsFilter {
+    pub field_size: Option<u16>,
+    pub category: Option<Category>,
+}
+
+impl StatsFilter {
+    pub fn matches(&self, result: &GameResult) -> bool {
+        self.field_size.is_none_or(|size| size == result.field_size)
+            && self.category.is_none_or(|category| category == result.category())
+    }
+}
+
+#[derive(Default, PartialEq, Debug)]
+pub struct Stats {
+    pub games: usize,
+    pub wins: usize,
+    // games played and won for every win value
+    pub win_values: BTreeMap<u32, (usize, usize)>,
+    pub best_score: u64,
+    pub best_tile: u32,
+    pub average_score: u64,
+    pub longest_game: usize,
+    pub total_merges: usize,
+    // how many games ended with each highest tile
+    pub max_tiles: BTreeMap<u32, usize>,
+    pub best_streak: usize,
+    pub current_streak: usize,
+}
+
+impl Stats {
+    // the history is in the order the games were played, which matters for the streaks
+    pub fn new(history: &[GameResult], filter: StatsFilter) -> Stats {
+        let mut stats = Stats::default();
+        let mut total_score = 0;
+
+        for result in history.iter().filter(|r| filter.matches(r)) {
+            stats.games += 1;
+            total_score += result.score;
+            stats.best_score = stats.best_score.max(result.score);
+            stats.best_tile = stats.best_tile.max(result.max_tile);
+            stats.longest_game = stats.longest_game.max(result.moves);
+            stats.total_merges += result.merges;
+            *stats.max_tiles.entry(result.max_tile).or_default() += 1;
+
+            let (played, won) = stats.win_values.entry(result.win_value).or_default();
+            *played += 1;
+            if result.won {
+                *won += 1;
+                stats.wins += 1;
+                stats.current_streak += 1;
+                stats.best_streak = stats.best_streak.max(stats.current_streak);
+            } else {
+                stats.current_streak = 0;
+            }
+        }
+
+        if stats.games > 0 {
+            stats.average_score = total_score / stats.games as u64;
+        }
+        stats
+    }
+}
+
+pub fn save_path() -> PathBuf {
+    paths::data_dir().join(SAVE_FILE)
+}
+
+pub fn read_history() -> Vec<GameResult> {
+    fs::read(paths::data_dir().join(HISTORY_FILE))
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+pub fn append_history(result: GameResult) -> io::Result<()> {
+    let mut history = read_history();
+    history.push(result);
+    fs::create_dir_all(paths::data_dir())?;
+    write_atomic(&paths::data_dir().join(HISTORY_FILE), &serde_json::to_vec(&history)?)
+}
+
+// named "{timestamp}-{seed}.json", with a counter added in the rare case that it is taken already
+pub fn write_replay(record: &GameRecord) -> io::Result<()> {
+    let dir = paths::data_dir().join(REPLAY_DIR);
+    fs::create_dir_all(&dir)?;
+    let name = format!("{}-{}", timestamp(), record.seed);
+    let path = (0..)
+        .map(|i| dir.join(if i == 0 { format!("{name}.json") } else { format!("{name}-{i}.json") }))
+        .find(|path| !path.exists())
+        .expect("some replay name is free");
+    record.write(&path)
+}
+
+// the time the game of a replay finished, from its file name
+pub fn replay_time(path: &Path) -> Option<u64> {
+    path.file_stem()?.to_str()?.split('-').next()?.parse().ok()
+}
+
+// newest first, files that can't be read are left out
+pub fn list_replays() -> Vec<(PathBuf, GameRecord)> {
+    let Ok(entries) = fs::read_dir(paths::data_dir().join(REPLAY_DIR)) else {
+        return Vec::new();
+    };
+
+    let mut replays = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|e| e == "json"))
+        .filter_map(|path| GameRecord::read(&path).ok().map(|record| (path, record)))
+        .collect::<Vec<_>>();
+    replays.sort_by(|a, b| b.0.cmp(&a.0));
+    replays
+}
+
+// the local date as "YYYY-MM-DD", so the daily challenge changes at midnight for everyone
+pub fn today() -> String {
+    Local::now().date_naive().format("%Y-%m-%d").to_string()
+}
+
+// an attempt counts as soon as it is started, so leaving it and starting over doesn't give another one
+pub fn daily_available(history: &[GameResult], date: &str) -> bool {
+    let started = fs::read_to_string(paths::data_dir().join(DAILY_STARTED_FILE)).ok();
+    started.as_deref().map(str::trim) != Some(date)
+        && !history.iter().any(|r| r.variant == Variant::Daily && r.date.as_deref() == Some(date))
+}
+
+pub fn mark_daily_started(date: &str) -> io::Result<()> {
+    fs::create_dir_all(paths::data_dir())?;
+    fs::write(paths::data_dir().join(DAILY_STARTED_FILE), date)
+}
+
+// FNV-1a, the seed must not depend on the platform or the version of the standard library
+pub fn daily_seed(date: &str) -> u64 {
+    format!("tui-2048 daily {date}").bytes().fold(0xcbf29ce484222325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
+
+// the text that is printed on exit, for comparing results
+pub fn daily_summary(result: &GameResult) -> String {
+    format!(
+        "tui-2048 daily {}: {} | score {} | max tile {} | {} moves",
+        result.date.as_deref().unwrap_or("?"),
+        if result.won { "won" } else { "lost" },
+        result.score,
+        result.max_tile,
+        result.moves,
+    )
+}
+
+pub fn timestamp() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+// "YYYY-MM-DD HH:MM" in local time, like the dates of the daily challenge
+pub fn format_timestamp(timestamp: u64) -> String {
+    DateTime::from_timestamp(timestamp as i64, 0)
+        .map(|time| time.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_default()
+}
+
+pub fn stats_report(history: &[GameResult]) -> String {
+    if history.is_empty() {
+        return String::from("No finished games yet.\n");
+    }
+
+    let stats = Stats::new(history, StatsFilter::default());
+    format!(
+        "Games played:  {}\nWins:          {}\nBest score:    {}\nBest tile:     {}\nAverage score: {}\nLongest game:  {} moves\nTotal merges:  {}\nBest streak:   {}\nStreak:        {}\n",
+        stats.games,
+        stats.wins,
+        stats.best_score,
+        stats.best_tile,
+        stats.average_score,
+        stats.longest_game,
+        stats.total_merges,
+        stats.best_streak,
+        stats.current_streak,
+    )
+}
+
+#[cfg(test)]
+mod date_test {
+    use super::{format_timestamp, timestamp, today};
+
+    #[test]
+    fn test_format_timestamp() {
+        // the day can change in between
+        let before = today();
+        let formatted = format_timestamp(timestamp());
+        let after = today();
+        assert!(formatted.starts_with(&before) || formatted.starts_with(&after));
+        assert_eq!("YYYY-MM-DD HH:MM".len(), formatted.len());
+    }
+}
+
+#[cfg(test)]
+mod stats_test {
+    use std::time::Duration;
+
+    use super::{Category, GameResult, Stats, StatsFilter, TIME_LIMITS, Variant};
+
+    fn result(field_size: u16, score: u64, max_tile: u32, won: bool) -> GameResult {
+        GameResult { timestamp: 0, field_size, win_value: 2048, score, max_tile, moves: score as usize / 10, won, merges: 3, variant: Variant::Classic, date: None, time_limit: None, move_limit: None }
+    }
+
+    #[test]
+    fn test_stats() {
+        let history = [
+            result(4, 100, 64, false),
+            result(4, 300, 2048, true),
+            result(5, 500, 2048, true),
+            result(4, 200, 128, false),
+            result(4, 400, 2048, true),
+        ];
+        let stats = Stats::new(&history, StatsFilter::default());
+        assert_eq!(5, stats.games);
+        assert_eq!(3, stats.wins);
+        assert_eq!(Some(&(5, 3)), stats.win_values.get(&2048));
+        assert_eq!(500, stats.best_score);
+        assert_eq!(300, stats.average_score);
+        assert_eq!(50, stats.longest_game);
+        assert_eq!(15, stats.total_merges);
+        assert_eq!(Some(&3), stats.max_tiles.get(&2048));
+        assert_eq!(2, stats.best_streak);
+        assert_eq!(1, stats.current_streak);
+    }
+
+    #[test]
+    fn test_stats_filter() {
+        let history = [result(4, 100, 64, false), result(5, 500, 2048, true)];
+        let stats = Stats::new(&history, StatsFilter { field_size: Some(4), category: None });
+        assert_eq!(1, stats.games);
+        assert_eq!(100, stats.best_score);
+        assert_eq!(0, stats.best_streak);
+
+        assert_eq!(Stats::default(), Stats::new(&[], StatsFilter::default()));
+    }
+
+    #[test]
+    fn test_time_limits_are_separate_categories() {
+        let blitz = |limit: Duration, score: u64| GameResult { variant: Variant::Blitz, time_limit: Some(limit), ..result(4, score, 64, false) };
+        let history = [blitz(TIME_LIMITS[0], 100), blitz(TIME_LIMITS[1], 500), result(4, 300, 64, false)];
+        let category = Category { variant: Variant::Blitz, time_limit: Some(TIME_LIMITS[0]), move_limit: None };
+        let stats = Stats::new(&history, StatsFilter { field_size: None, category: Some(category) });
+        assert_eq!(1, stats.games);
+        assert_eq!(100, stats.best_score);
+
+        assert!(Category::all().contains(&category));
+        assert_eq!("blitz 2:00", category.name());
+        assert_eq!(3, Stats::new(&history, StatsFilter::default()).games);
+    }
+
+    #[test]
+    fn test_read_old_results() {
+        let result: GameResult = serde_json::from_str(r#"{"timestamp":1,"field_size":4,"win_value":2048,"score":10,"max_tile":8,"moves":3,"won":false}"#).unwrap();
+        assert_eq!(0, result.merges);
+        assert_eq!(Variant::Classic, result.variant);
+    }
+}
+
+#[cfg(test)]
+mod daily_test {
+    use super::{daily_seed, daily_summary, GameResult, Variant};
+
+    #[test]
+    fn test_daily_seed() {
+        assert_eq!(daily_seed("2024-03-01"), daily_seed("2024-03-01"));
+        assert_ne!(daily_seed("2024-03-01"), daily_seed("2024-03-02"));
+    }
+
+    #[test]
+    fn test_daily_summary() {
+        let result = GameResult { timestamp: 0, field_size: 4, win_value: 2048, score: 1234, max_tile: 128, moves: 99, won: false, merges: 0, variant: Variant::Daily, date: Some(String::from("2024-03-01")), time_limit: None, move_limit: None };
+        assert_eq!("tui-2048 daily 2024-03-01: lost | score 1234 | max tile 128 | 99 moves", daily_summary(&result));
+    }
+}
+
+#[cfg(test)]
+mod record_test {
+    use std::path::Path;
+
+    use super::{GameRecord, replay_time};
+
+    fn record(extra: &str) -> GameRecord {
+        serde_json::from_str(&format!(r#"{{"seed":1,"field_size":4,"win_value":2048,"moves":[]{extra}}}"#)).unwrap()
+    }
+
+    #[test]
+    fn test_validate_board() {
+        assert!(record("").validate().is_ok());
+        assert!(record(r#","board":[[2,0,0],[0,0,0],[0,0,4]]"#).validate().is_ok());
+        assert!(record(r#","board":[[2,2],[0]]"#).validate().is_err());
+        assert!(record(r#","board":[[2,2,0],[0,0,0]]"#).validate().is_err());
+        assert!(record(r#","board":[]"#).validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_puzzle() {
+        let puzzle = |goal: u32| format!(r#","puzzle":{{"name":"p","board":[[2,2,0],[0,0,0],[0,0,0]],"goal":{{"type":"reach","tile":{goal}}}}}"#);
+        assert!(record(&puzzle(8)).validate().is_ok());
+        assert!(record(&puzzle(2)).validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_spawns() {
+        let spawns = r#","spawns":{"weights":{"2":1},"per_move":1,"initial":2,"position":"uniform"}"#;
+        assert!(record(spawns).validate().is_ok());
+        assert!(record(&spawns.replace(r#"{"2":1}"#, "{}")).validate().is_err());
+        assert!(record(&spawns.replace(r#""initial":2"#, r#""initial":0"#)).validate().is_err());
+    }
+
+    #[test]
+    fn test_replay_time() {
+        assert_eq!(Some(1700000000), replay_time(Path::new("replays/1700000000-42.json")));
+        assert_eq!(Some(1700000000), replay_time(Path::new("replays/1700000000-42-1.json")));
+        // replays written before the seed was part of the name
+        assert_eq!(Some(1700000000), replay_time(Path::new("replays/1700000000.json")));
+        assert_eq!(None, replay_time(Path::new("replays/notes.json")));
+    }
+}
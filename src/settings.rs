@@ -0,0 +1,390 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde_json::{json, Map, Value};
+
+use crossterm::event::KeyCode;
+
+use crate::{Config, MouseConfig};
+use crate::cli::Overrides;
+use crate::colors::Theme;
+use crate::keymap::{KeyBinding, KeyMap};
+
+pub const CONFIG_VERSION: u64 = 2;
+
+// MIGRATIONS[n] upgrades a config document from version n to n + 1
+const MIGRATIONS: [fn(&mut Map<String, Value>); CONFIG_VERSION as usize] = [
+    migrate_v0_to_v1,
+    migrate_v1_to_v2,
+];
+
+// files without a version are from before versioning was introduced
+fn migrate_v0_to_v1(document: &mut Map<String, Value>) {
+    document.entry("theme").or_insert(json!(Theme::default()));
+    document.entry("mouse").or_insert(json!(MouseConfig::default()));
+}
+
+// keymaps used to be lists of key codes per action, now they only contain what differs from a preset
+fn migrate_v1_to_v2(document: &mut Map<String, Value>) {
+    const V1_DEFAULTS: [(&str, &[&str]); 9] = [
+        ("up", &["w", "Up"]),
+        ("down", &["s", "Down"]),
+        ("left", &["a", "Left"]),
+        ("right", &["d", "Right"]),
+        ("exit", &["q", "Esc"]),
+        ("reset", &["r"]),
+        ("confirm", &["Enter"]),
+        ("back", &["Backspace"]),
+        ("config", &["c"]),
+    ];
+
+    let mut keymap = json!(KeyMap::default());
+    match document.remove("keymap") {
+        Some(Value::Object(old)) => {
+            let mut global = Map::new();
+            for (action, codes) in old {
+                let Value::Array(codes) = codes else { continue };
+                let chords = codes.into_iter()
+                    .filter_map(|code| serde_json::from_value::<KeyCode>(code).ok())
+                    .map(|code| KeyBinding::key(code).to_string())
+                    .collect::<Vec<_>>();
+
+                if !V1_DEFAULTS.iter().any(|(name, keys)| *name == action && *keys == chords) {
+                    global.insert(action, json!(chords));
+                }
+            }
+            keymap["global"] = Value::Object(global);
+        }
+        // not a keymap at all, reported when the config is read
+        Some(invalid) => keymap = invalid,
+        None => {}
+    }
+    document.insert(String::from("keymap"), keymap);
+}
+
+pub fn migrate(document: &mut Map<String, Value>) -> u64 {
+    let version = document.get("version").and_then(Value::as_u64).unwrap_or(0);
+    for migration in MIGRATIONS.iter().skip(version as usize) {
+        migration(document);
+    }
+    document.insert(String::from("version"), json!(version.max(CONFIG_VERSION)));
+    version
+}
+
+// upgrades the config file to the current version, the old file is kept as a backup
+// returns a message for the user if anything was done
+pub fn migrate_file(path: &Path) -> io::Result<Option<String>> {
+    let contents = fs::read_to_string(path)?;
+    let Ok(mut document) = serde_json::from_str::<Map<String, Value>>(&contents) else {
+        // reported when the config is read
+        return Ok(None);
+    };
+
+    let version = document.get("version").and_then(Value::as_u64).unwrap_or(0);
+    if version > CONFIG_VERSION {
+        return Ok(Some(format!("{} is from a newer version of tui-2048 (config version {version}), some settings might be ignored", path.display())));
+    }
+    if version == CONFIG_VERSION {
+        return Ok(None);
+    }
+
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(format!(".v{version}.bak"));
+    fs::copy(path, &backup)?;
+
+    migrate(&mut document);
+    write_document(path, &document, &contents)?;
+    Ok(Some(format!("Upgraded {} from config version {version} to {CONFIG_VERSION}, the old file was saved as {}", path.display(), Path::new(&backup).display())))
+}
+
+// loads the config file and notices when it changes, so it can be applied while the game is running
+pub struct ConfigWatcher {
+    pub path: PathBuf,
+    overrides: Overrides,
+    modified: Option<SystemTime>,
+}
+
+impl ConfigWatcher {
+    pub fn new(path: PathBuf, overrides: Overrides) -> Self {
+        let mut watcher = Self { path, overrides, modified: None };
+        watcher.refresh();
+        watcher
+    }
+
+    // polls the modification time, there is no need for a file system notification service
+    pub fn changed(&mut self) -> bool {
+        let modified = self.modification_time();
+        if modified == self.modified {
+            return false;
+        }
+        self.modified = modified;
+        true
+    }
+
+    pub fn overrides(&self, key: &str) -> bool {
+        self.overrides.keys().contains(&key)
+    }
+
+    // called after writing the file ourselves, so it isn't reloaded
+    pub fn refresh(&mut self) {
+        self.modified = self.modification_time();
+    }
+
+    fn modification_time(&self) -> Option<SystemTime> {
+        fs::metadata(&self.path).and_then(|m| m.modified()).ok()
+    }
+
+    // never fails, problems are returned as messages for the user
+    pub fn load(&mut self) -> (Config, Vec<String>) {
+        let mut notices = Vec::new();
+        match migrate_file(&self.path) {
+            Ok(Some(message)) => notices.push(message),
+            Ok(None) => {}
+            Err(e) => notices.push(format!("Could not upgrade {}: {e}", self.path.display())),
+        }
+
+        let mut errors = Vec::new();
+        let mut config = match config::Config::builder()
+            .add_source(config::File::from(self.path.as_path()))
+            .build() {
+            Ok(file) => Config::read_file(file, &mut errors),
+            Err(e) => {
+                errors.push(e.to_string());
+                Config::default()
+            }
+        };
+        if !errors.is_empty() {
+            notices.push(format!("Problems in {}, the defaults are used instead:", self.path.display()));
+            notices.append(&mut errors);
+        }
+
+        // upgrading the file changed it
+        self.refresh();
+        self.overrides.apply(&mut config);
+        (config, notices)
+    }
+}
+
+pub fn write_default(path: &Path) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, serde_json::to_vec(&Config::default())?)
+}
+
+// writes a single changed setting back to the config file, so command line overrides aren't persisted by accident
+// unknown keys, the key order and whether the file is pretty printed are kept
+pub fn save_setting(path: &Path, config: &Config, key: &str) -> io::Result<()> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => serde_json::to_string(config)?,
+        Err(e) => return Err(e),
+    };
+    // a file that can't be parsed is left alone, overwriting it would lose whatever the user wrote
+    let mut document: Map<String, Value> = serde_json::from_str(&contents)?;
+
+    let value = json!(config).get(key).cloned().ok_or(io::Error::new(io::ErrorKind::InvalidInput, format!("unknown setting {key}")))?;
+    document.insert(String::from(key), value);
+
+    write_document(path, &document, &contents)
+}
+
+// takes the given settings over from the previous config, so changes that only apply for this session survive a reload
+pub fn keep_settings<'a>(config: Config, previous: &Config, keys: impl IntoIterator<Item = &'a String>) -> Config {
+    let previous = json!(previous);
+    let mut document = json!(config);
+    for key in keys {
+        if let Some(value) = previous.get(key) {
+            document[key] = value.clone();
+        }
+    }
+    serde_json::from_value(document).unwrap_or(config)
+}
+
+// keeps the file pretty printed if the previous contents were
+fn write_document(path: &Path, document: &Map<String, Value>, previous: &str) -> io::Result<()> {
+    let output = if previous.trim_end().contains('\n') {
+        serde_json::to_string_pretty(document)?
+    } else {
+        serde_json::to_string(document)?
+    };
+    write_atomic(path, output.as_bytes())
+}
+
+// the file is either completely written or not changed at all
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+
+    fs::write(&tmp, contents)?;
+    fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod save_test {
+    use std::fs;
+    use std::path::PathBuf;
+    use std::time::{Duration, SystemTime};
+
+    use serde_json::{json, Map, Value};
+
+    use crate::cli::Overrides;
+    use crate::colors::Theme;
+    use crate::Config;
+    use super::{CONFIG_VERSION, ConfigWatcher, keep_settings, migrate, migrate_file, save_setting};
+
+    fn temp_file(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("tui-2048-{}-{name}", std::process::id()));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_save_keeps_unknown_keys_and_order() {
+        let path = temp_file("order.json", "{\n  \"win_value\": 3,\n  \"custom\": { \"a\": 1 },\n  \"field_size\": 1\n}\n");
+        let mut config = Config::default();
+        config.field_size = 4;
+        config.theme = Theme::Rainbow;
+
+        save_setting(&path, &config, "field_size").unwrap();
+        save_setting(&path, &config, "theme").unwrap();
+        let saved = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(saved.contains('\n'));
+        assert!(saved.find("win_value").unwrap() < saved.find("custom").unwrap());
+        assert!(saved.find("custom").unwrap() < saved.find("field_size").unwrap());
+        assert!(saved.contains("\"field_size\": 4"));
+        assert!(saved.contains("\"theme\": \"Rainbow\""));
+        assert!(saved.contains("\"a\": 1"));
+        assert!(saved.contains("\"win_value\": 3"));
+    }
+
+    #[test]
+    fn test_keep_settings() {
+        let mut previous = Config::default();
+        previous.theme = Theme::Rainbow;
+        previous.field_size = 4;
+        let mut config = Config::default();
+        config.win_value = 3;
+
+        let kept = keep_settings(config, &previous, &[String::from("theme")]);
+        assert_eq!(Theme::Rainbow, kept.theme);
+        assert_eq!(Config::default().field_size, kept.field_size);
+        assert_eq!(3, kept.win_value);
+    }
+
+    #[test]
+    fn test_save_keeps_compact_files_compact() {
+        let path = temp_file("compact.json", "{\"field_size\":1}");
+        save_setting(&path, &Config::default(), "field_size").unwrap();
+        let saved = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(!saved.contains('\n'));
+    }
+
+    #[test]
+    fn test_save_leaves_broken_files_alone() {
+        let path = temp_file("broken.json", "{\"field_size\":");
+        assert!(save_setting(&path, &Config::default(), "field_size").is_err());
+        let saved = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!("{\"field_size\":", saved);
+    }
+
+    // the config format before versioning was introduced
+    const V0_CONFIG: &str = r#"{"keymap":{"up":[{"Char":"w"},"Up"],"down":[{"Char":"s"},"Down"],"left":[{"Char":"a"},"Left"],"right":[{"Char":"d"},"Right"],"exit":[{"Char":"q"},"Esc"],"reset":[{"Char":"r"}],"confirm":["Enter"],"back":["Backspace"],"config":[{"Char":"c"}]},"colors":{"buffer_bg":"Black","header_bg":"Blue","header_fg":"Green","row_fg":"White","selected_style_fg":"Gray","normal_row_color":"Black","footer_border_color":"Green"},"field_size":2,"win_value":8,"reset_popup":true,"ending_animation":true,"control_info":false}"#;
+
+    #[test]
+    fn test_migrate_v0() {
+        let mut document: Map<String, Value> = serde_json::from_str(V0_CONFIG).unwrap();
+        assert_eq!(0, migrate(&mut document));
+
+        assert_eq!(Some(&json!(CONFIG_VERSION)), document.get("version"));
+        assert_eq!(Some(&json!(Theme::Classic)), document.get("theme"));
+        assert!(document.get("mouse").unwrap().get("drag").is_some());
+        assert_eq!(Some(&json!(2)), document.get("field_size"));
+        assert_eq!(Some(&json!(false)), document.get("control_info"));
+        assert_eq!(Some(&json!("default")), document["keymap"].get("preset"));
+        assert_eq!(Some(&json!({})), document["keymap"].get("global"));
+    }
+
+    #[test]
+    fn test_migrate_v1_keymap() {
+        let mut document: Map<String, Value> = serde_json::from_str(r#"{"version":1,"keymap":{"up":[{"Char":"i"},"Up"],"down":[{"Char":"s"},"Down"],"exit":["Esc"]}}"#).unwrap();
+        assert_eq!(1, migrate(&mut document));
+
+        let global = &document["keymap"]["global"];
+        assert_eq!(&json!(["i", "Up"]), &global["up"]);
+        assert_eq!(&json!(["Esc"]), &global["exit"]);
+        assert!(global.get("down").is_none());
+    }
+
+    #[test]
+    fn test_migrate_current_is_unchanged() {
+        let mut document = json!(Config::default()).as_object().unwrap().clone();
+        let before = document.clone();
+        assert_eq!(CONFIG_VERSION, migrate(&mut document));
+        assert_eq!(before, document);
+    }
+
+    #[test]
+    fn test_migrate_file_with_backup() {
+        let path = temp_file("migrate.json", V0_CONFIG);
+        let mut backup = path.as_os_str().to_owned();
+        backup.push(".v0.bak");
+
+        assert!(migrate_file(&path).unwrap().is_some());
+        assert!(migrate_file(&path).unwrap().is_none());
+        let migrated = fs::read_to_string(&path).unwrap();
+        let saved_backup = fs::read_to_string(&backup).unwrap();
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&backup).unwrap();
+
+        assert_eq!(V0_CONFIG, saved_backup);
+        assert!(migrated.contains(&format!("\"version\":{CONFIG_VERSION}")));
+    }
+
+    #[test]
+    fn test_migrate_file_from_newer_version() {
+        let path = temp_file("newer.json", r#"{"version":999}"#);
+        assert!(migrate_file(&path).unwrap().is_some());
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(r#"{"version":999}"#, contents);
+    }
+
+    #[test]
+    fn test_watcher_notices_changes() {
+        let path = temp_file("watch.json", &format!(r#"{{"version":{CONFIG_VERSION},"theme":"Classic"}}"#));
+        let mut watcher = ConfigWatcher::new(path.clone(), Overrides::default());
+        assert!(!watcher.changed());
+
+        fs::write(&path, format!(r#"{{"version":{CONFIG_VERSION},"theme":"Rainbow"}}"#)).unwrap();
+        let file = fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(10)).unwrap();
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+
+        let (config, notices) = watcher.load();
+        fs::remove_file(&path).unwrap();
+        assert!(notices.is_empty());
+        assert_eq!(Theme::Rainbow, config.theme);
+    }
+
+    #[test]
+    fn test_watcher_applies_overrides() {
+        let path = temp_file("overrides.json", &format!(r#"{{"version":{CONFIG_VERSION},"theme":"Rainbow","field_size":1}}"#));
+        let overrides = Overrides { size: Some(3), theme: Some(Theme::Tritanopia), ..Overrides::default() };
+
+        let (config, _) = ConfigWatcher::new(path.clone(), overrides).load();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(Theme::Tritanopia, config.theme);
+        assert_eq!(3, config.field_size);
+    }
+}
@@ -1,50 +1,327 @@
 use std::cmp::Ordering::{Equal, Greater, Less};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 use config::Value;
 use crossterm::event::KeyCode;
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
 
 use itertools::Itertools;
-use rand::{Rng, thread_rng};
-use ratatui::prelude::Color;
+use lazy_static::lazy_static;
+use rand::{Rng, SeedableRng, thread_rng};
+use rand_chacha::ChaCha8Rng;
 use regex::Regex;
+use unicode_width::UnicodeWidthStr;
 
 use crate::{Data, Direction, movement};
+use crate::keymap::Chord;
 use crate::Direction::*;
-use crate::movement::rotate;
+use crate::movement::{BLOCKER, MergeRule, rotate};
 
-pub const INFO_TEXT: &str =
-    "(Esc) quit | (↑) move up | (↓) move Down | (→) move right \n| (←) move left";
+const INFO_SEPARATOR: &str = " | ";
 
 static SCORE: AtomicU64 = AtomicU64::new(0);
 static HIGHSCORE: AtomicU64 = AtomicU64::new(0);
 
-pub fn generate_data() -> Vec<Data> {
-    let mut ret = (0..4)
+lazy_static! {
+    // all spawns are drawn from this, so a game can be reproduced from its seed and moves
+    // the algorithm is fixed, unlike the one of StdRng, so daily boards and replays stay the same with newer versions of rand
+    static ref RNG: Mutex<ChaCha8Rng> = Mutex::new(ChaCha8Rng::from_entropy());
+}
+
+pub fn new_seed() -> u64 {
+    thread_rng().gen()
+}
+
+pub fn seed_rng(seed: u64) {
+    *RNG.lock().unwrap() = ChaCha8Rng::seed_from_u64(seed);
+}
+
+// where new tiles appear, the restricted ones fall back to any empty field when all of their fields are taken
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpawnPosition {
+    Uniform,
+    Corners,
+    // the edge the tiles moved away from, so new tiles end up behind them
+    OppositeEdge,
+    // the field that leaves the fewest empty fields after the best next move, for a hard game
+    Adversarial,
+}
+
+impl SpawnPosition {
+    pub const ALL: [SpawnPosition; 4] = [SpawnPosition::Uniform, SpawnPosition::Corners, SpawnPosition::OppositeEdge, SpawnPosition::Adversarial];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SpawnPosition::Uniform => "anywhere",
+            SpawnPosition::Corners => "corners",
+            SpawnPosition::OppositeEdge => "opposite edge",
+            SpawnPosition::Adversarial => "worst (hard)",
+        }
+    }
+}
+
+// part of the config and of every record, so replays spawn the same tiles even after the config changed
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct SpawnRules {
+    // relative chances, values with a weight of 0 never spawn
+    pub weights: BTreeMap<u32, u32>,
+    pub per_move: usize,
+    pub initial: usize,
+    pub position: SpawnPosition,
+}
+
+impl SpawnRules {
+    // the rules of the original game
+    pub fn default() -> Self {
+        Self {
+            weights: Self::weights(MergeRule::Doubling.spawn_values()),
+            per_move: 1,
+            initial: 2,
+            position: SpawnPosition::Uniform,
+        }
+    }
+
+    // the common value is five times as likely as the rare one
+    fn weights((common, rare): (u32, u32)) -> BTreeMap<u32, u32> {
+        if common == rare {
+            BTreeMap::from([(common, 1)])
+        } else {
+            BTreeMap::from([(common, 5), (rare, 1)])
+        }
+    }
+
+    // keeps everything but the values, for rules that don't work with powers of two
+    pub fn with_values(self, values: (u32, u32)) -> Self {
+        Self { weights: Self::weights(values), ..self }
+    }
+
+    pub fn from_map(mut map: ConfigTable) -> Self {
+        let default = Self::default();
+        let weights = match map.get::<Option<HashMap<String, u32>>>("weights", None) {
+            Some(raw) => {
+                let mut weights = BTreeMap::new();
+                for (value, weight) in raw {
+                    match value.parse::<u32>().map_err(|_| String::from("has to be a power of two")).and_then(|tile| check_value(tile, MergeRule::Doubling).map(|_| tile)) {
+                        Ok(tile) => { weights.insert(tile, weight); }
+                        Err(e) => map.error(&format!("weights.{value}"), e),
+                    }
+                }
+                match check_weights(&weights) {
+                    Ok(()) => weights,
+                    Err(e) => {
+                        map.error("weights", e);
+                        default.weights
+                    }
+                }
+            }
+            None => default.weights,
+        };
+
+        fn at_least_one(map: &mut ConfigTable, key: &str, default: usize) -> usize {
+            let value = map.get(key, default);
+            match check_count(value) {
+                Ok(()) => value,
+                Err(e) => {
+                    map.error(key, e);
+                    default
+                }
+            }
+        }
+        Self {
+            weights,
+            per_move: at_least_one(&mut map, "per_move", default.per_move),
+            initial: at_least_one(&mut map, "initial", default.initial),
+            position: map.get("position", default.position),
+        }
+    }
+
+    // the same checks as for the config, for rules that come from a record
+    pub fn validate(&self, rule: MergeRule) -> Result<(), String> {
+        for value in self.weights.keys() {
+            check_value(*value, rule).map_err(|e| format!("weights.{value}: {e}"))?;
+        }
+        check_weights(&self.weights).map_err(|e| format!("weights: {e}"))?;
+        check_count(self.per_move).map_err(|e| format!("per_move: {e}"))?;
+        check_count(self.initial).map_err(|e| format!("initial: {e}"))
+    }
+
+    // for the help screen
+    pub fn describe(&self) -> String {
+        let total: u32 = self.weights.values().sum();
+        let values = self.weights.iter()
+            .filter(|(_, weight)| **weight > 0)
+            .map(|(value, weight)| if *weight == total { value.to_string() } else { format!("{value}: {}%", (weight * 100 + total / 2) / total) })
+            .join(", ");
+        let tiles = if self.per_move == 1 { format!("a new tile ({values}) appears") } else { format!("{} new tiles ({values}) appear", self.per_move) };
+        let position = match self.position {
+            SpawnPosition::Uniform => "on an empty field",
+            SpawnPosition::Corners => "in an empty corner",
+            SpawnPosition::OppositeEdge => "on the edge the tiles moved away from",
+            SpawnPosition::Adversarial => "on the field that is worst for you",
+        };
+        format!("{tiles} {position}")
+    }
+}
+
+// the other rules bring their own values, see with_values
+fn check_value(value: u32, rule: MergeRule) -> Result<(), String> {
+    match rule {
+        MergeRule::Doubling if value < 2 || !value.is_power_of_two() => Err(String::from("has to be a power of two")),
+        _ if value == 0 || value == BLOCKER => Err(String::from("has to be a tile value")),
+        _ => Ok(()),
+    }
+}
+
+// spawn_value needs something to draw from
+fn check_weights(weights: &BTreeMap<u32, u32>) -> Result<(), String> {
+    if weights.values().all(|weight| *weight == 0) {
+        return Err(String::from("at least one value needs a weight above 0"));
+    }
+    Ok(())
+}
+
+fn check_count(count: usize) -> Result<(), String> {
+    if count == 0 {
+        return Err(String::from("has to be at least 1"));
+    }
+    Ok(())
+}
+
+pub fn generate_data(size: usize, rules: &SpawnRules, rule: MergeRule) -> Vec<Data> {
+    let mut ret = (0..size)
         .map(|_| {
             Data {
-                numbers: vec![0, 0, 0, 0],
+                numbers: vec![0; size],
             }
         })
         .collect_vec();
 
-    spawn_field(&mut ret);
-    spawn_field(&mut ret);
+    spawn_tiles(&mut ret, rules, rule, None);
 
     ret
 }
 
-pub fn spawn_field(vec: &mut [Data]) {
-    let mut index = thread_rng().gen_range(0..16);
-    while vec[index / 4].numbers[index % 4] != 0 {
-        index = thread_rng().gen_range(0..16);
+// the tiles after a move into the given direction, or the ones a game starts with without a direction
+// stops early when the board is full
+pub fn spawn_tiles(vec: &mut [Data], rules: &SpawnRules, rule: MergeRule, dir: Option<Direction>) {
+    let count = if dir.is_some() { rules.per_move } else { rules.initial };
+    for _ in 0..count {
+        if !vec.iter().any(|row| row.numbers.contains(&0)) {
+            return;
+        }
+        spawn_field(vec, rules, rule, dir);
     }
+}
 
-    vec[index / 4].numbers[index % 4] = if thread_rng().gen_ratio(1, 6) {
-        4
-    } else {
-        2
+// the field has to have at least one empty field
+// the merge rule decides which moves the player has left, only the adversarial position looks at them
+pub fn spawn_field(vec: &mut [Data], rules: &SpawnRules, rule: MergeRule, dir: Option<Direction>) {
+    let mut rng = RNG.lock().unwrap();
+    let (row, column, value) = match (rules.position, dir) {
+        // which field is the worst depends on the value, so it is drawn first
+        (SpawnPosition::Adversarial, Some(_)) => {
+            let value = spawn_value(&mut rng, &rules.weights);
+            let (row, column) = worst_field(&mut rng, vec, value, rule);
+            (row, column, value)
+        }
+        _ => {
+            let candidates = spawn_candidates(vec, rules.position, dir);
+            let (row, column) = if candidates.is_empty() {
+                empty_field(&mut rng, vec)
+            } else {
+                candidates[rng.gen_range(0..candidates.len())]
+            };
+            (row, column, spawn_value(&mut rng, &rules.weights))
+        }
     };
+
+    vec[row].numbers[column] = value;
+}
+
+// the empty fields the position allows, an empty list means any empty field
+fn spawn_candidates(vec: &[Data], position: SpawnPosition, dir: Option<Direction>) -> Vec<(usize, usize)> {
+    let last = vec.len() - 1;
+    let fields = match (position, dir) {
+        (SpawnPosition::Corners, _) => vec![(0, 0), (0, last), (last, 0), (last, last)],
+        (SpawnPosition::OppositeEdge, Some(Left)) => (0..=last).map(|row| (row, last)).collect(),
+        (SpawnPosition::OppositeEdge, Some(Right)) => (0..=last).map(|row| (row, 0)).collect(),
+        (SpawnPosition::OppositeEdge, Some(Up)) => (0..=last).map(|column| (last, column)).collect(),
+        (SpawnPosition::OppositeEdge, Some(Down)) => (0..=last).map(|column| (0, column)).collect(),
+        (SpawnPosition::Uniform | SpawnPosition::Adversarial, _) | (SpawnPosition::OppositeEdge, None) => Vec::new(),
+    };
+    fields.into_iter().filter(|(row, column)| vec[*row].numbers[*column] == 0).collect()
+}
+
+// a search one move deep: every empty field is tried and the one where the player's best answer leaves the fewest empty fields wins
+// placements that leave no move at all are the worst, equally bad fields are chosen at random
+fn worst_field(rng: &mut ChaCha8Rng, vec: &[Data], value: u32, rule: MergeRule) -> (usize, usize) {
+    let size = vec.len();
+    let empty = (0..size * size).map(|index| (index / size, index % size)).filter(|(row, column)| vec[*row].numbers[*column] == 0).collect_vec();
+    let replies = empty.iter().map(|(row, column)| {
+        let mut field = vec.to_vec();
+        field[*row].numbers[*column] = value;
+        best_reply(&field, rule)
+    }).collect_vec();
+
+    let worst = replies.iter().min().unwrap();
+    let fields = empty.iter().zip(replies.iter()).filter(|(_, reply)| *reply == worst).map(|(field, _)| *field).collect_vec();
+    fields[rng.gen_range(0..fields.len())]
+}
+
+// the most empty fields any move can leave, None if no move is possible
+fn best_reply(field: &[Data], rule: MergeRule) -> Option<usize> {
+    [Left, Right, Up, Down].into_iter()
+        .map(|dir| moved_field(field, dir, rule))
+        .filter(|moved| moved != field)
+        .map(|moved| moved.iter().flat_map(|row| row.numbers.iter()).filter(|value| **value == 0).count())
+        .max()
+}
+
+// one chance per value from the highest down, so the default rules draw exactly like the original 1 in 6 for a 4
+fn spawn_value(rng: &mut ChaCha8Rng, weights: &BTreeMap<u32, u32>) -> u32 {
+    let weights = weights.iter().filter(|(_, weight)| **weight > 0).collect_vec();
+    let mut total: u32 = weights.iter().map(|(_, weight)| **weight).sum();
+    for (value, weight) in weights.iter().skip(1).rev() {
+        if rng.gen_ratio(**weight, total) {
+            return **value;
+        }
+        total -= **weight;
+    }
+    *weights[0].0
+}
+
+// puts the blockers on random empty fields, as many as fit
+pub fn spawn_blockers(vec: &mut [Data], count: usize) {
+    let mut rng = RNG.lock().unwrap();
+    let empty = vec.iter().flat_map(|row| row.numbers.iter()).filter(|value| **value == 0).count();
+    for _ in 0..count.min(empty) {
+        let (row, column) = empty_field(&mut rng, vec);
+        vec[row].numbers[column] = BLOCKER;
+    }
+}
+
+// the field has to have at least one empty field
+fn empty_field(rng: &mut ChaCha8Rng, vec: &[Data]) -> (usize, usize) {
+    let size = vec.len();
+
+    let mut index = rng.gen_range(0..size * size);
+    while vec[index / size].numbers[index % size] != 0 {
+        index = rng.gen_range(0..size * size);
+    }
+    (index / size, index % size)
+}
+
+// blockers aren't tiles
+pub fn max_tile(field: &[Data]) -> u32 {
+    field.iter().flat_map(|row| row.numbers.iter().copied()).filter(|value| *value != BLOCKER).max().unwrap_or(0)
+}
+
+pub fn tile_count(field: &[Data]) -> usize {
+    field.iter().flat_map(|row| row.numbers.iter()).filter(|value| **value != 0 && **value != BLOCKER).count()
 }
 
 pub fn check_win(field: &[Data], win_value: &u32) -> bool {
@@ -55,8 +332,8 @@ pub fn check_win(field: &[Data], win_value: &u32) -> bool {
     false
 }
 
-pub fn check_loss(field: &[Data]) -> bool {
-    !(check_move(field, Left) || check_move(field, Right) || check_move(field, Up) || check_move(field, Down) || check_empty(field))
+pub fn check_loss(field: &[Data], rule: MergeRule) -> bool {
+    !(check_move(field, Left, rule) || check_move(field, Right, rule) || check_move(field, Up, rule) || check_move(field, Down, rule) || check_empty(field))
 }
 
 fn check_empty(field: &[Data]) -> bool {
@@ -68,7 +345,12 @@ fn check_empty(field: &[Data]) -> bool {
 }
 
 // checks if the game field changes in any way after moving in to a given direction
-pub fn check_move(field: &[Data], dir: Direction) -> bool {
+pub fn check_move(field: &[Data], dir: Direction, rule: MergeRule) -> bool {
+    *field != moved_field(field, dir, rule)
+}
+
+// the field after moving into the given direction, without any spawns
+fn moved_field(field: &[Data], dir: Direction, rule: MergeRule) -> Vec<Data> {
     let mut new_items = Vec::<Data>::new();
 
     if dir == Up || dir == Down {
@@ -77,7 +359,7 @@ pub fn check_move(field: &[Data], dir: Direction) -> bool {
         rotate(clone, dir == Up);
 
         for row in clone.iter() {
-            new_items.push(Data { numbers: movement::slide_left(row.numbers().as_slice()) });
+            new_items.push(Data { numbers: movement::slide_left(row.numbers().as_slice(), rule) });
         }
 
         rotate(new_items.as_mut_slice(), dir == Down);
@@ -85,15 +367,34 @@ pub fn check_move(field: &[Data], dir: Direction) -> bool {
         for row in field.iter() {
             new_items.push(Data {
                 numbers: if dir == Left {
-                    movement::slide_left(row.numbers().as_slice())
+                    movement::slide_left(row.numbers().as_slice(), rule)
                 } else {
-                    movement::slide_right(row.numbers().as_slice())
+                    movement::slide_right(row.numbers().as_slice(), rule)
                 }
             });
         }
     }
 
-    *field != new_items
+    new_items
+}
+
+// translates a mouse drag from start to end (column, row) into a move direction
+// terminal cells are about twice as high as they are wide, so vertical distances count double
+pub fn drag_direction(start: (u16, u16), end: (u16, u16), min_distance: u16) -> Option<Direction> {
+    let dx = end.0 as i32 - start.0 as i32;
+    let dy = (end.1 as i32 - start.1 as i32) * 2;
+
+    if dx.abs().max(dy.abs()) < min_distance as i32 {
+        return None;
+    }
+
+    Some(if dx.abs() >= dy.abs() {
+        if dx > 0 { Right } else { Left }
+    } else if dy > 0 {
+        Down
+    } else {
+        Up
+    })
 }
 
 // thank you stack overflow
@@ -120,14 +421,70 @@ pub fn remove_matches(v1: &mut Vec<u32>, v2: &mut Vec<u32>) {
     }
 }
 
-#[inline]
-pub fn deserialize_keycode_vec(map: &HashMap<String, Value>, key: &str) -> Vec<KeyCode> {
-    map.get(key).unwrap().clone().into_array().unwrap().iter_mut().map(|x| x.clone().try_deserialize::<KeyCode>().unwrap()).collect_vec()
+// a table of the config file, missing values fall back to their defaults
+// and invalid ones are additionally reported with their full key path
+pub struct ConfigTable<'a> {
+    map: HashMap<String, Value>,
+    path: String,
+    errors: &'a mut Vec<String>,
 }
 
-#[inline]
-pub fn deserialize_color(map: &HashMap<String, Value>, key: &str) -> Color {
-    map.get(key).unwrap().clone().try_deserialize::<Color>().unwrap()
+impl<'a> ConfigTable<'a> {
+    pub fn new(map: HashMap<String, Value>, errors: &'a mut Vec<String>) -> Self {
+        Self { map, path: String::new(), errors }
+    }
+
+    pub fn get<T: DeserializeOwned>(&mut self, key: &str, default: T) -> T {
+        match self.map.get(key) {
+            Some(value) => value.clone().try_deserialize::<T>().unwrap_or_else(|e| {
+                self.error(key, e.to_string());
+                default
+            }),
+            None => default,
+        }
+    }
+
+    // for indices into FIELD_SIZES and WIN_VALUES
+    pub fn get_index(&mut self, key: &str, default: usize, len: usize) -> usize {
+        let index = self.get(key, default);
+        if index >= len {
+            self.error(key, format!("{index} is out of range, expected 0 to {}", len - 1));
+            return default;
+        }
+        index
+    }
+
+    // invalid bindings are skipped, if none is left the action keeps the bindings it would have without this key
+    pub fn get_chords(&mut self, key: &str) -> Option<Vec<Chord>> {
+        let values = self.get::<Option<Vec<String>>>(key, None)?;
+
+        let mut chords = Vec::with_capacity(values.len());
+        for (i, value) in values.iter().enumerate() {
+            match value.parse::<Chord>() {
+                Ok(chord) => chords.push(chord),
+                Err(e) => self.error(&format!("{key}[{i}]"), e),
+            }
+        }
+
+        if chords.is_empty() && !values.is_empty() {
+            self.error(key, String::from("no valid keys, using the default ones"));
+            return None;
+        }
+        Some(chords)
+    }
+
+    pub fn table(&mut self, key: &str) -> ConfigTable<'_> {
+        let map = self.get::<Option<HashMap<String, Value>>>(key, None).unwrap_or_default();
+        ConfigTable {
+            map,
+            path: format!("{}{key}.", self.path),
+            errors: self.errors,
+        }
+    }
+
+    pub fn error(&mut self, key: &str, message: String) {
+        self.errors.push(format!("{}{key}: {message}", self.path));
+    }
 }
 
 pub fn format_keycode(k: &KeyCode) -> String {
@@ -139,13 +496,96 @@ pub fn format_keycode(k: &KeyCode) -> String {
         KeyCode::Down => String::from("↓"),
         KeyCode::Left => String::from("←"),
         KeyCode::Right => String::from("→"),
-        _ => match re.captures(&*raw) {
+        KeyCode::F(n) => format!("F{n}"),
+        _ => match re.captures(&raw) {
             Some(c) => c.get(1).unwrap().as_str().to_string().to_uppercase(),
             None => raw
         }
     }
 }
 
+// lays out "(keys) label" entries in at most max_lines lines of the given width,
+// if they don't fit only the first key of each entry is shown and finally the rest is cut off
+pub fn control_info(entries: &[(Vec<String>, &str)], width: usize, max_lines: usize) -> Vec<String> {
+    let entries = entries.iter().filter(|(keys, _)| !keys.is_empty()).collect_vec();
+    let all_keys = entries.iter().map(|(keys, label)| format!("({}) {label}", keys.join("/"))).collect_vec();
+    let first_key = entries.iter().map(|(keys, label)| format!("({}) {label}", keys[0])).collect_vec();
+
+    for items in [&all_keys, &first_key] {
+        let lines = wrap_items(items, width);
+        if lines.len() <= max_lines {
+            return lines;
+        }
+    }
+
+    let mut lines = wrap_items(&first_key, width);
+    lines.truncate(max_lines);
+    if let Some(last) = lines.last_mut() {
+        while !last.is_empty() && last.width() + 1 > width {
+            last.pop();
+        }
+        last.push('…');
+    }
+    lines
+}
+
+fn wrap_items(items: &[String], width: usize) -> Vec<String> {
+    let mut lines = Vec::<String>::new();
+    for item in items {
+        match lines.last_mut() {
+            Some(line) if line.width() + INFO_SEPARATOR.width() + item.width() <= width => {
+                line.push_str(INFO_SEPARATOR);
+                line.push_str(item);
+            }
+            _ => lines.push(item.clone()),
+        }
+    }
+    lines
+}
+
+// the biggest score increase of a single move, given the score after every move
+pub fn largest_gain(score_history: &[u64]) -> u64 {
+    std::iter::once(&0).chain(score_history).tuple_windows().map(|(a, b)| b.saturating_sub(*a)).max().unwrap_or(0)
+}
+
+// the time a game has been played, without the pauses
+pub struct GameTimer {
+    elapsed: Duration,
+    running_since: Option<Instant>,
+}
+
+impl GameTimer {
+    // starts paused with the time played before
+    pub fn new(elapsed: Duration) -> Self {
+        Self { elapsed, running_since: None }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed + self.running_since.map(|since| since.elapsed()).unwrap_or_default()
+    }
+
+    pub fn set_running(&mut self, running: bool) {
+        match (self.running_since, running) {
+            (None, true) => self.running_since = Some(Instant::now()),
+            (Some(since), false) => {
+                self.elapsed += since.elapsed();
+                self.running_since = None;
+            }
+            _ => {}
+        }
+    }
+}
+
+// "m:ss", or "h:mm:ss" for games longer than an hour
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    if seconds >= 3600 {
+        format!("{}:{:02}:{:02}", seconds / 3600, seconds % 3600 / 60, seconds % 60)
+    } else {
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
 pub fn set_score(num: u64) {
     SCORE.store(num, Ordering::SeqCst);
 }
@@ -184,6 +624,7 @@ mod check_test {
     use super::check_win;
     use super::check_move;
     use super::Direction::*;
+    use super::MergeRule;
 
     lazy_static! {
         // 4x4 fields
@@ -450,235 +891,430 @@ mod check_test {
 
     #[test]
     fn test_check_loss_4x4_empty() {
-        assert!(!check_loss(EMPTY_4X4_FIELD.deref()));
+        assert!(!check_loss(EMPTY_4X4_FIELD.deref(), MergeRule::Doubling));
     }
 
     #[test]
     fn test_check_loss_4x4_starting() {
-        assert!(!check_loss(STARTING_4X4_FIELD.deref()));
+        assert!(!check_loss(STARTING_4X4_FIELD.deref(), MergeRule::Doubling));
     }
 
     #[test]
     fn test_check_loss_4x4_mixed() {
-        assert!(!check_loss(MIXED_4X4_FIELD.deref()));
+        assert!(!check_loss(MIXED_4X4_FIELD.deref(), MergeRule::Doubling));
     }
 
     #[test]
     fn test_check_loss_4x4_filled() {
-        assert!(!check_loss(FILLED_4X4_FIELD.deref()));
+        assert!(!check_loss(FILLED_4X4_FIELD.deref(), MergeRule::Doubling));
     }
 
     #[test]
     fn test_check_loss_4x4_blocked() {
-        assert!(check_loss(BLOCKED_4X4_FIELD.deref()));
+        assert!(check_loss(BLOCKED_4X4_FIELD.deref(), MergeRule::Doubling));
     }
 
     #[test]
     fn test_check_loss_4x4_win() {
-        assert!(!check_loss(WIN_2048_4X4_FIELD.deref()));
+        assert!(!check_loss(WIN_2048_4X4_FIELD.deref(), MergeRule::Doubling));
     }
 
     #[test]
     fn test_check_loss_3x3_empty() {
-        assert!(!check_loss(EMPTY_3X3_FIELD.deref()));
+        assert!(!check_loss(EMPTY_3X3_FIELD.deref(), MergeRule::Doubling));
     }
 
     #[test]
     fn test_check_loss_3x3_starting() {
-        assert!(!check_loss(STARTING_3X3_FIELD.deref()));
+        assert!(!check_loss(STARTING_3X3_FIELD.deref(), MergeRule::Doubling));
     }
 
     #[test]
     fn test_check_loss_3x3_mixed() {
-        assert!(!check_loss(MIXED_3X3_FIELD.deref()));
+        assert!(!check_loss(MIXED_3X3_FIELD.deref(), MergeRule::Doubling));
     }
 
     #[test]
     fn test_check_loss_3x3_filled() {
-        assert!(!check_loss(FILLED_3X3_FIELD.deref()));
+        assert!(!check_loss(FILLED_3X3_FIELD.deref(), MergeRule::Doubling));
     }
 
     #[test]
     fn test_check_loss_3x3_blocked() {
-        assert!(check_loss(BLOCKED_3X3_FIELD.deref()));
+        assert!(check_loss(BLOCKED_3X3_FIELD.deref(), MergeRule::Doubling));
     }
 
     #[test]
     fn test_check_loss_3x3_win() {
-        assert!(!check_loss(WIN_256_3X3_FIELD.deref()));
+        assert!(!check_loss(WIN_256_3X3_FIELD.deref(), MergeRule::Doubling));
     }
 
     #[test]
     fn test_check_loss_5x5_empty() {
-        assert!(!check_loss(EMPTY_5X5_FIELD.deref()));
+        assert!(!check_loss(EMPTY_5X5_FIELD.deref(), MergeRule::Doubling));
     }
 
     #[test]
     fn test_check_loss_5x5_starting() {
-        assert!(!check_loss(STARTING_5X5_FIELD.deref()));
+        assert!(!check_loss(STARTING_5X5_FIELD.deref(), MergeRule::Doubling));
     }
 
     #[test]
     fn test_check_loss_5x5_mixed() {
-        assert!(!check_loss(MIXED_5X5_FIELD.deref()));
+        assert!(!check_loss(MIXED_5X5_FIELD.deref(), MergeRule::Doubling));
     }
 
     #[test]
     fn test_check_loss_5x5_filled() {
-        assert!(!check_loss(FILLED_5X5_FIELD.deref()));
+        assert!(!check_loss(FILLED_5X5_FIELD.deref(), MergeRule::Doubling));
     }
 
     #[test]
     fn test_check_loss_5x5_blocked() {
-        assert!(check_loss(BLOCKED_5X5_FIELD.deref()));
+        assert!(check_loss(BLOCKED_5X5_FIELD.deref(), MergeRule::Doubling));
     }
 
     #[test]
     fn test_check_loss_5x5_win() {
-        assert!(!check_loss(WIN_4096_5X5_FIELD.deref()));
+        assert!(!check_loss(WIN_4096_5X5_FIELD.deref(), MergeRule::Doubling));
     }
 
     #[test]
     fn test_check_move_4x4_empty() {
-        assert!(!check_move(EMPTY_4X4_FIELD.deref(), Left));
-        assert!(!check_move(EMPTY_4X4_FIELD.deref(), Right));
-        assert!(!check_move(EMPTY_4X4_FIELD.deref(), Up));
-        assert!(!check_move(EMPTY_4X4_FIELD.deref(), Down));
+        assert!(!check_move(EMPTY_4X4_FIELD.deref(), Left, MergeRule::Doubling));
+        assert!(!check_move(EMPTY_4X4_FIELD.deref(), Right, MergeRule::Doubling));
+        assert!(!check_move(EMPTY_4X4_FIELD.deref(), Up, MergeRule::Doubling));
+        assert!(!check_move(EMPTY_4X4_FIELD.deref(), Down, MergeRule::Doubling));
     }
 
     #[test]
     fn test_check_move_4x4_starting() {
-        assert!(check_move(STARTING_4X4_FIELD.deref(), Left));
-        assert!(check_move(STARTING_4X4_FIELD.deref(), Right));
-        assert!(check_move(STARTING_4X4_FIELD.deref(), Up));
-        assert!(check_move(STARTING_4X4_FIELD.deref(), Down));
+        assert!(check_move(STARTING_4X4_FIELD.deref(), Left, MergeRule::Doubling));
+        assert!(check_move(STARTING_4X4_FIELD.deref(), Right, MergeRule::Doubling));
+        assert!(check_move(STARTING_4X4_FIELD.deref(), Up, MergeRule::Doubling));
+        assert!(check_move(STARTING_4X4_FIELD.deref(), Down, MergeRule::Doubling));
     }
 
     #[test]
     fn test_check_move_4x4_mixed() {
-        assert!(check_move(MIXED_4X4_FIELD.deref(), Left));
-        assert!(check_move(MIXED_4X4_FIELD.deref(), Right));
-        assert!(check_move(MIXED_4X4_FIELD.deref(), Up));
-        assert!(check_move(MIXED_4X4_FIELD.deref(), Down));
+        assert!(check_move(MIXED_4X4_FIELD.deref(), Left, MergeRule::Doubling));
+        assert!(check_move(MIXED_4X4_FIELD.deref(), Right, MergeRule::Doubling));
+        assert!(check_move(MIXED_4X4_FIELD.deref(), Up, MergeRule::Doubling));
+        assert!(check_move(MIXED_4X4_FIELD.deref(), Down, MergeRule::Doubling));
     }
 
     #[test]
     fn test_check_move_4x4_filled() {
-        assert!(!check_move(FILLED_4X4_FIELD.deref(), Left));
-        assert!(!check_move(FILLED_4X4_FIELD.deref(), Right));
-        assert!(check_move(FILLED_4X4_FIELD.deref(), Up));
-        assert!(check_move(FILLED_4X4_FIELD.deref(), Down));
+        assert!(!check_move(FILLED_4X4_FIELD.deref(), Left, MergeRule::Doubling));
+        assert!(!check_move(FILLED_4X4_FIELD.deref(), Right, MergeRule::Doubling));
+        assert!(check_move(FILLED_4X4_FIELD.deref(), Up, MergeRule::Doubling));
+        assert!(check_move(FILLED_4X4_FIELD.deref(), Down, MergeRule::Doubling));
     }
 
     #[test]
     fn test_check_move_4x4_blocked() {
-        assert!(!check_move(BLOCKED_4X4_FIELD.deref(), Left));
-        assert!(!check_move(BLOCKED_4X4_FIELD.deref(), Right));
-        assert!(!check_move(BLOCKED_4X4_FIELD.deref(), Up));
-        assert!(!check_move(BLOCKED_4X4_FIELD.deref(), Down));
+        assert!(!check_move(BLOCKED_4X4_FIELD.deref(), Left, MergeRule::Doubling));
+        assert!(!check_move(BLOCKED_4X4_FIELD.deref(), Right, MergeRule::Doubling));
+        assert!(!check_move(BLOCKED_4X4_FIELD.deref(), Up, MergeRule::Doubling));
+        assert!(!check_move(BLOCKED_4X4_FIELD.deref(), Down, MergeRule::Doubling));
     }
 
     #[test]
     fn test_check_move_4x4_win() {
-        assert!(check_move(WIN_2048_4X4_FIELD.deref(), Left));
-        assert!(check_move(WIN_2048_4X4_FIELD.deref(), Right));
-        assert!(check_move(WIN_2048_4X4_FIELD.deref(), Up));
-        assert!(check_move(WIN_2048_4X4_FIELD.deref(), Down));
+        assert!(check_move(WIN_2048_4X4_FIELD.deref(), Left, MergeRule::Doubling));
+        assert!(check_move(WIN_2048_4X4_FIELD.deref(), Right, MergeRule::Doubling));
+        assert!(check_move(WIN_2048_4X4_FIELD.deref(), Up, MergeRule::Doubling));
+        assert!(check_move(WIN_2048_4X4_FIELD.deref(), Down, MergeRule::Doubling));
     }
 
     #[test]
     fn test_check_move_3x3_empty() {
-        assert!(!check_move(EMPTY_3X3_FIELD.deref(), Left));
-        assert!(!check_move(EMPTY_3X3_FIELD.deref(), Right));
-        assert!(!check_move(EMPTY_3X3_FIELD.deref(), Up));
-        assert!(!check_move(EMPTY_3X3_FIELD.deref(), Down));
+        assert!(!check_move(EMPTY_3X3_FIELD.deref(), Left, MergeRule::Doubling));
+        assert!(!check_move(EMPTY_3X3_FIELD.deref(), Right, MergeRule::Doubling));
+        assert!(!check_move(EMPTY_3X3_FIELD.deref(), Up, MergeRule::Doubling));
+        assert!(!check_move(EMPTY_3X3_FIELD.deref(), Down, MergeRule::Doubling));
     }
 
     #[test]
     fn test_check_move_3x3_starting() {
-        assert!(check_move(STARTING_3X3_FIELD.deref(), Left));
-        assert!(check_move(STARTING_3X3_FIELD.deref(), Right));
-        assert!(check_move(STARTING_3X3_FIELD.deref(), Up));
-        assert!(check_move(STARTING_3X3_FIELD.deref(), Down));
+        assert!(check_move(STARTING_3X3_FIELD.deref(), Left, MergeRule::Doubling));
+        assert!(check_move(STARTING_3X3_FIELD.deref(), Right, MergeRule::Doubling));
+        assert!(check_move(STARTING_3X3_FIELD.deref(), Up, MergeRule::Doubling));
+        assert!(check_move(STARTING_3X3_FIELD.deref(), Down, MergeRule::Doubling));
     }
 
     #[test]
     fn test_check_move_3x3_mixed() {
-        assert!(check_move(MIXED_3X3_FIELD.deref(), Left));
-        assert!(check_move(MIXED_3X3_FIELD.deref(), Right));
-        assert!(!check_move(MIXED_3X3_FIELD.deref(), Up));
-        assert!(check_move(MIXED_3X3_FIELD.deref(), Down));
+        assert!(check_move(MIXED_3X3_FIELD.deref(), Left, MergeRule::Doubling));
+        assert!(check_move(MIXED_3X3_FIELD.deref(), Right, MergeRule::Doubling));
+        assert!(!check_move(MIXED_3X3_FIELD.deref(), Up, MergeRule::Doubling));
+        assert!(check_move(MIXED_3X3_FIELD.deref(), Down, MergeRule::Doubling));
     }
 
     #[test]
     fn test_check_move_3x3_filled() {
-        assert!(check_move(FILLED_3X3_FIELD.deref(), Left));
-        assert!(check_move(FILLED_3X3_FIELD.deref(), Right));
-        assert!(!check_move(FILLED_3X3_FIELD.deref(), Up));
-        assert!(!check_move(FILLED_3X3_FIELD.deref(), Down));
+        assert!(check_move(FILLED_3X3_FIELD.deref(), Left, MergeRule::Doubling));
+        assert!(check_move(FILLED_3X3_FIELD.deref(), Right, MergeRule::Doubling));
+        assert!(!check_move(FILLED_3X3_FIELD.deref(), Up, MergeRule::Doubling));
+        assert!(!check_move(FILLED_3X3_FIELD.deref(), Down, MergeRule::Doubling));
     }
 
     #[test]
     fn test_check_move_3x3_blocked() {
-        assert!(!check_move(BLOCKED_3X3_FIELD.deref(), Left));
-        assert!(!check_move(BLOCKED_3X3_FIELD.deref(), Right));
-        assert!(!check_move(BLOCKED_3X3_FIELD.deref(), Up));
-        assert!(!check_move(BLOCKED_3X3_FIELD.deref(), Down));
+        assert!(!check_move(BLOCKED_3X3_FIELD.deref(), Left, MergeRule::Doubling));
+        assert!(!check_move(BLOCKED_3X3_FIELD.deref(), Right, MergeRule::Doubling));
+        assert!(!check_move(BLOCKED_3X3_FIELD.deref(), Up, MergeRule::Doubling));
+        assert!(!check_move(BLOCKED_3X3_FIELD.deref(), Down, MergeRule::Doubling));
     }
 
     #[test]
     fn test_check_move_3x3_win() {
-        assert!(check_move(WIN_256_3X3_FIELD.deref(), Left));
-        assert!(check_move(WIN_256_3X3_FIELD.deref(), Right));
-        assert!(check_move(WIN_256_3X3_FIELD.deref(), Up));
-        assert!(check_move(WIN_256_3X3_FIELD.deref(), Down));
+        assert!(check_move(WIN_256_3X3_FIELD.deref(), Left, MergeRule::Doubling));
+        assert!(check_move(WIN_256_3X3_FIELD.deref(), Right, MergeRule::Doubling));
+        assert!(check_move(WIN_256_3X3_FIELD.deref(), Up, MergeRule::Doubling));
+        assert!(check_move(WIN_256_3X3_FIELD.deref(), Down, MergeRule::Doubling));
     }
 
     #[test]
     fn test_check_move_5x5_empty() {
-        assert!(!check_move(EMPTY_5X5_FIELD.deref(), Left));
-        assert!(!check_move(EMPTY_5X5_FIELD.deref(), Right));
-        assert!(!check_move(EMPTY_5X5_FIELD.deref(), Up));
-        assert!(!check_move(EMPTY_5X5_FIELD.deref(), Down));
+        assert!(!check_move(EMPTY_5X5_FIELD.deref(), Left, MergeRule::Doubling));
+        assert!(!check_move(EMPTY_5X5_FIELD.deref(), Right, MergeRule::Doubling));
+        assert!(!check_move(EMPTY_5X5_FIELD.deref(), Up, MergeRule::Doubling));
+        assert!(!check_move(EMPTY_5X5_FIELD.deref(), Down, MergeRule::Doubling));
     }
 
     #[test]
     fn test_check_move_5x5_starting() {
-        assert!(check_move(STARTING_5X5_FIELD.deref(), Left));
-        assert!(check_move(STARTING_5X5_FIELD.deref(), Right));
-        assert!(check_move(STARTING_5X5_FIELD.deref(), Up));
-        assert!(check_move(STARTING_5X5_FIELD.deref(), Down));
+        assert!(check_move(STARTING_5X5_FIELD.deref(), Left, MergeRule::Doubling));
+        assert!(check_move(STARTING_5X5_FIELD.deref(), Right, MergeRule::Doubling));
+        assert!(check_move(STARTING_5X5_FIELD.deref(), Up, MergeRule::Doubling));
+        assert!(check_move(STARTING_5X5_FIELD.deref(), Down, MergeRule::Doubling));
     }
 
     #[test]
     fn test_check_move_5x5_mixed() {
-        assert!(check_move(MIXED_5X5_FIELD.deref(), Left));
-        assert!(check_move(MIXED_5X5_FIELD.deref(), Right));
-        assert!(check_move(MIXED_5X5_FIELD.deref(), Up));
-        assert!(check_move(MIXED_5X5_FIELD.deref(), Down));
+        assert!(check_move(MIXED_5X5_FIELD.deref(), Left, MergeRule::Doubling));
+        assert!(check_move(MIXED_5X5_FIELD.deref(), Right, MergeRule::Doubling));
+        assert!(check_move(MIXED_5X5_FIELD.deref(), Up, MergeRule::Doubling));
+        assert!(check_move(MIXED_5X5_FIELD.deref(), Down, MergeRule::Doubling));
     }
 
     #[test]
     fn test_check_move_5x5_filled() {
-        assert!(!check_move(FILLED_5X5_FIELD.deref(), Left));
-        assert!(!check_move(FILLED_5X5_FIELD.deref(), Right));
-        assert!(check_move(FILLED_5X5_FIELD.deref(), Up));
-        assert!(check_move(FILLED_5X5_FIELD.deref(), Down));
+        assert!(!check_move(FILLED_5X5_FIELD.deref(), Left, MergeRule::Doubling));
+        assert!(!check_move(FILLED_5X5_FIELD.deref(), Right, MergeRule::Doubling));
+        assert!(check_move(FILLED_5X5_FIELD.deref(), Up, MergeRule::Doubling));
+        assert!(check_move(FILLED_5X5_FIELD.deref(), Down, MergeRule::Doubling));
     }
 
     #[test]
     fn test_check_move_5x5_blocked() {
-        assert!(!check_move(BLOCKED_5X5_FIELD.deref(), Left));
-        assert!(!check_move(BLOCKED_5X5_FIELD.deref(), Right));
-        assert!(!check_move(BLOCKED_5X5_FIELD.deref(), Up));
-        assert!(!check_move(BLOCKED_5X5_FIELD.deref(), Down));
+        assert!(!check_move(BLOCKED_5X5_FIELD.deref(), Left, MergeRule::Doubling));
+        assert!(!check_move(BLOCKED_5X5_FIELD.deref(), Right, MergeRule::Doubling));
+        assert!(!check_move(BLOCKED_5X5_FIELD.deref(), Up, MergeRule::Doubling));
+        assert!(!check_move(BLOCKED_5X5_FIELD.deref(), Down, MergeRule::Doubling));
     }
 
     #[test]
     fn test_check_move_5x5_win() {
-        assert!(check_move(WIN_4096_5X5_FIELD.deref(), Left));
-        assert!(check_move(WIN_4096_5X5_FIELD.deref(), Right));
-        assert!(check_move(WIN_4096_5X5_FIELD.deref(), Up));
-        assert!(check_move(WIN_4096_5X5_FIELD.deref(), Down));
+        assert!(check_move(WIN_4096_5X5_FIELD.deref(), Left, MergeRule::Doubling));
+        assert!(check_move(WIN_4096_5X5_FIELD.deref(), Right, MergeRule::Doubling));
+        assert!(check_move(WIN_4096_5X5_FIELD.deref(), Up, MergeRule::Doubling));
+        assert!(check_move(WIN_4096_5X5_FIELD.deref(), Down, MergeRule::Doubling));
+    }
+}
+
+#[cfg(test)]
+mod spawn_test {
+    use std::collections::BTreeMap;
+
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    use crate::Data;
+    use super::Direction::*;
+    use super::{best_reply, empty_field, MergeRule, spawn_candidates, spawn_value, SpawnPosition, SpawnRules, worst_field};
+
+    fn field() -> Vec<Data> {
+        vec![
+            Data { numbers: vec![2, 0, 0] },
+            Data { numbers: vec![0, 4, 0] },
+            Data { numbers: vec![0, 0, 8] },
+        ]
+    }
+
+    #[test]
+    fn test_default_values_draw_like_the_original() {
+        let weights = SpawnRules::default().weights;
+        let mut original = ChaCha8Rng::seed_from_u64(7);
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+        for _ in 0..100 {
+            let expected = if original.gen_ratio(1, 6) { 4 } else { 2 };
+            assert_eq!(expected, spawn_value(&mut rng, &weights));
+        }
     }
-}
\ No newline at end of file
+
+    // daily boards and replays depend on these, a change here means the rng algorithm changed
+    #[test]
+    fn test_spawns_are_pinned() {
+        let weights = SpawnRules::default().weights;
+        let mut rng = ChaCha8Rng::seed_from_u64(2048);
+        let values = (0..12).map(|_| spawn_value(&mut rng, &weights)).collect::<Vec<_>>();
+        let fields = (0..4).map(|_| empty_field(&mut rng, &field())).collect::<Vec<_>>();
+        assert_eq!(vec![2, 2, 2, 2, 2, 2, 4, 2, 2, 2, 4, 2], values);
+        assert_eq!(vec![(1, 2), (1, 0), (0, 1), (0, 1)], fields);
+    }
+
+    #[test]
+    fn test_zero_weights_never_spawn() {
+        let weights = BTreeMap::from([(2, 0), (4, 3), (8, 0)]);
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+        assert!((0..100).all(|_| spawn_value(&mut rng, &weights) == 4));
+    }
+
+    #[test]
+    fn test_validate() {
+        let rules = |weights: BTreeMap<u32, u32>| SpawnRules { weights, ..SpawnRules::default() };
+        assert!(SpawnRules::default().validate(MergeRule::Doubling).is_ok());
+        assert!(rules(BTreeMap::new()).validate(MergeRule::Doubling).is_err());
+        assert!(rules(BTreeMap::from([(2, 0), (4, 0)])).validate(MergeRule::Doubling).is_err());
+        assert!(rules(BTreeMap::from([(3, 1)])).validate(MergeRule::Doubling).is_err());
+        assert!(rules(BTreeMap::from([(3, 1)])).validate(MergeRule::Triples).is_ok());
+        assert!(rules(BTreeMap::from([(0, 1)])).validate(MergeRule::Fibonacci).is_err());
+        assert!(SpawnRules { per_move: 0, ..SpawnRules::default() }.validate(MergeRule::Doubling).is_err());
+        assert!(SpawnRules { initial: 0, ..SpawnRules::default() }.validate(MergeRule::Doubling).is_err());
+    }
+
+    #[test]
+    fn test_spawn_candidates() {
+        assert!(spawn_candidates(&field(), SpawnPosition::Uniform, Some(Left)).is_empty());
+        assert_eq!(vec![(0, 2), (2, 0)], spawn_candidates(&field(), SpawnPosition::Corners, None));
+        assert_eq!(vec![(0, 2), (1, 2)], spawn_candidates(&field(), SpawnPosition::OppositeEdge, Some(Left)));
+        assert_eq!(vec![(0, 1), (0, 2)], spawn_candidates(&field(), SpawnPosition::OppositeEdge, Some(Down)));
+        // the tiles a game starts with can be anywhere
+        assert!(spawn_candidates(&field(), SpawnPosition::OppositeEdge, None).is_empty());
+    }
+
+    #[test]
+    fn test_worst_field() {
+        let field = vec![
+            Data { numbers: vec![4, 2, 4] },
+            Data { numbers: vec![2, 4, 8] },
+            Data { numbers: vec![0, 16, 0] },
+        ];
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+        // an 8 on the right could merge with the one above it, on the left it leaves a single empty field
+        assert_eq!((2, 0), worst_field(&mut rng, &field, 8, MergeRule::Doubling));
+        assert_eq!(Some(1), best_reply(&[Data { numbers: vec![8, 16, 0] }, field[0].clone(), field[1].clone()], MergeRule::Doubling));
+        assert_eq!(None, best_reply(&[Data { numbers: vec![2, 4] }, Data { numbers: vec![4, 2] }], MergeRule::Doubling));
+    }
+
+    #[test]
+    fn test_describe() {
+        assert_eq!("a new tile (2: 83%, 4: 17%) appears on an empty field", SpawnRules::default().describe());
+        let rules = SpawnRules { per_move: 2, position: SpawnPosition::Corners, ..SpawnRules::default().with_values((3, 3)) };
+        assert_eq!("2 new tiles (3) appear in an empty corner", rules.describe());
+    }
+}
+
+#[cfg(test)]
+mod drag_test {
+    use super::drag_direction;
+    use super::Direction::*;
+
+    #[test]
+    fn test_drag_too_short() {
+        assert_eq!(None, drag_direction((10, 10), (10, 10), 3));
+        assert_eq!(None, drag_direction((10, 10), (12, 10), 3));
+        assert_eq!(None, drag_direction((10, 10), (10, 11), 3));
+    }
+
+    #[test]
+    fn test_drag_horizontal() {
+        assert_eq!(Some(Right), drag_direction((10, 10), (20, 11), 3));
+        assert_eq!(Some(Left), drag_direction((10, 10), (0, 9), 3));
+    }
+
+    #[test]
+    fn test_drag_vertical() {
+        assert_eq!(Some(Down), drag_direction((10, 10), (12, 14), 3));
+        assert_eq!(Some(Up), drag_direction((10, 10), (8, 6), 3));
+    }
+
+    #[test]
+    fn test_drag_vertical_counts_double() {
+        // 4 columns vs. 3 rows: the rows win since they are twice as high
+        assert_eq!(Some(Down), drag_direction((10, 10), (14, 13), 3));
+        assert_eq!(Some(Up), drag_direction((10, 10), (10, 8), 3));
+    }
+}
+
+#[cfg(test)]
+mod control_info_test {
+    use super::control_info;
+
+    fn entries() -> Vec<(Vec<String>, &'static str)> {
+        vec![
+            (vec![String::from("Q"), String::from("Esc")], "quit"),
+            (vec![String::from("W"), String::from("↑")], "up"),
+            (vec![], "unbound"),
+            (vec![String::from("R")], "reset"),
+        ]
+    }
+
+    #[test]
+    fn test_control_info_single_line() {
+        assert_eq!(vec!["(Q/Esc) quit | (W/↑) up | (R) reset"], control_info(&entries(), 80, 3));
+    }
+
+    #[test]
+    fn test_control_info_wraps() {
+        assert_eq!(vec!["(Q/Esc) quit | (W/↑) up", "(R) reset"], control_info(&entries(), 24, 3));
+    }
+
+    #[test]
+    fn test_control_info_abbreviates() {
+        assert_eq!(vec!["(Q) quit | (W) up", "(R) reset"], control_info(&entries(), 18, 2));
+    }
+
+    #[test]
+    fn test_control_info_cuts_off() {
+        assert_eq!(vec!["(Q) quit", "(W) up…"], control_info(&entries(), 9, 2));
+    }
+}
+
+#[cfg(test)]
+mod summary_test {
+    use std::time::Duration;
+
+    use super::{format_duration, GameTimer, largest_gain};
+
+    #[test]
+    fn test_largest_gain() {
+        assert_eq!(0, largest_gain(&[]));
+        assert_eq!(4, largest_gain(&[4]));
+        assert_eq!(16, largest_gain(&[4, 4, 8, 24, 28]));
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!("0:07", format_duration(Duration::from_secs(7)));
+        assert_eq!("12:34", format_duration(Duration::from_secs(754)));
+        assert_eq!("1:02:03", format_duration(Duration::from_secs(3723)));
+    }
+
+    #[test]
+    fn test_timer_pauses() {
+        let mut timer = GameTimer::new(Duration::from_secs(5));
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(Duration::from_secs(5), timer.elapsed());
+
+        timer.set_running(true);
+        std::thread::sleep(Duration::from_millis(20));
+        timer.set_running(false);
+        let paused = timer.elapsed();
+        assert!(paused >= Duration::from_millis(5020));
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(paused, timer.elapsed());
+    }
+}